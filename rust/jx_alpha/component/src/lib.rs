@@ -51,7 +51,7 @@ const DYNAMIC_MODE: TypeSpecificInfoRef<'static, &'static str> = TypeSpecificInf
     ],
 };
 
-const PARAMETERS: [InfoRef<'static, &'static str>; 55] = [
+const PARAMETERS: [InfoRef<'static, &'static str>; 57] = [
     InfoRef {
         title: "Level",
         short_title: "Level",
@@ -298,6 +298,23 @@ const PARAMETERS: [InfoRef<'static, &'static str>; 55] = [
         flags: Flags { automatable: true },
         type_specific: DYNAMIC_MODE,
     },
+    InfoRef {
+        title: "VCF Model",
+        short_title: "VCF Model",
+        unique_id: "vcf_model",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["SVF", "Ladder"],
+        },
+    },
+    InfoRef {
+        title: "VCF FM",
+        short_title: "VCF FM",
+        unique_id: "vcf_fm",
+        flags: Flags { automatable: true },
+        type_specific: percentage(0.0),
+    },
     InfoRef {
         title: "VCA Env Source",
         short_title: "VCA Env Source",
@@ -479,3 +496,188 @@ impl ComponentTrait for Component {
         synth::Synth::new(env)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Component;
+    use component_snapshots::{
+        ProcessingParams,
+        synth::{generate_basic_snapshot, generate_snapshot, get_single_note_events},
+    };
+    use conformal_component::{
+        ProcessingMode,
+        audio::all_approx_eq,
+        events::{Data, Event, NoteData, NoteID},
+        parameters::InternalValue,
+    };
+    use dsp::{
+        osc_utils::increment,
+        test_utils::{estimate_aliasing, windowed_rfft},
+    };
+    use more_asserts::{assert_gt, assert_lt};
+    use snapshots::assert_snapshot;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_offline() {
+        let snapshot = generate_snapshot(
+            &Component {},
+            48000,
+            &ProcessingParams {
+                processing_mode: ProcessingMode::Offline,
+                ..Default::default()
+            },
+            &HashMap::new(),
+            &get_single_note_events(48000),
+        );
+        assert_snapshot!("offline", 48000, snapshot);
+    }
+
+    fn high_to_low_energy_ratio(processing_mode: ProcessingMode) -> f32 {
+        let mut output = generate_snapshot(
+            &Component {},
+            16384,
+            &ProcessingParams {
+                processing_mode,
+                ..Default::default()
+            },
+            &HashMap::from([("dco1_shape", InternalValue::Enum(4))]),
+            &get_single_note_events(16384),
+        );
+        let spectrum = windowed_rfft(&mut output[4096..12288]);
+        let band_energy = |bins: std::ops::Range<usize>| {
+            spectrum[bins]
+                .iter()
+                .fold(0.0, |acc, bin| acc + bin.norm_sqr())
+        };
+
+        // Bins are ~5.9hz wide, so this compares 17-19khz to 1-3khz.
+        band_energy(2901..3243) / band_energy(171..512)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn offline_rendering_preserves_high_frequencies() {
+        // Rendering offline runs the VCF at a higher rate, so when fully open it
+        // should roll off less before nyquist.
+        let realtime = high_to_low_energy_ratio(ProcessingMode::Realtime);
+        let offline = high_to_low_energy_ratio(ProcessingMode::Offline);
+        assert_gt!(offline, realtime * 1.1);
+    }
+
+    fn high_note_aliasing(processing_mode: ProcessingMode, pitch: u8) -> f32 {
+        let events = [Event {
+            sample_offset: 0,
+            data: Data::NoteOn {
+                data: NoteData {
+                    id: NoteID::from_pitch(pitch),
+                    pitch,
+                    velocity: 1.0,
+                    tuning: 0.0,
+                },
+            },
+        }];
+        let mut output = generate_snapshot(
+            &Component {},
+            16384,
+            &ProcessingParams {
+                processing_mode,
+                ..Default::default()
+            },
+            &HashMap::from([
+                ("vcf_cutoff", InternalValue::Numeric(100.0)),
+                ("vcf_env", InternalValue::Numeric(0.0)),
+                ("mix_dco1", InternalValue::Numeric(0.0)),
+                ("mix_dco2", InternalValue::Numeric(100.0)),
+                // Hard sync resets DCO2 mid-cycle, which is hard to band-limit.
+                ("x_mod", InternalValue::Enum(3)),
+                ("dco2_tune", InternalValue::Enum(24)),
+            ]),
+            &events.into_iter(),
+        );
+        // Skip the attack, and measure slightly below the fundamental so the
+        // window's main lobe isn't counted as aliasing.
+        estimate_aliasing(
+            &mut output[8192..],
+            0.95 * increment(f32::from(pitch), 48000.0),
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn offline_rendering_reduces_aliasing() {
+        // Running at twice the rate moves about half of the aliased energy above the
+        // decimation filter, so we expect a reduction of about 5.5dB at every pitch.
+        for pitch in [88, 100, 104, 112] {
+            let realtime = high_note_aliasing(ProcessingMode::Realtime, pitch);
+            let offline = high_note_aliasing(ProcessingMode::Offline, pitch);
+            assert_lt!(offline, realtime - 5.0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_ladder() {
+        let snapshot = generate_basic_snapshot(
+            &Component {},
+            48000,
+            &HashMap::from([
+                ("vcf_model", InternalValue::Enum(1)),
+                ("vcf_cutoff", InternalValue::Numeric(40.0)),
+                ("resonance", InternalValue::Numeric(90.0)),
+            ]),
+        );
+        assert_snapshot!("ladder", 48000, snapshot);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn vcf_fm_modulates_cutoff() {
+        // DCO2 is not mixed in, so it can only affect the output through the VCF.
+        let with_fm = |fm| {
+            generate_basic_snapshot(
+                &Component {},
+                4096,
+                &HashMap::from([
+                    ("vcf_cutoff", InternalValue::Numeric(40.0)),
+                    ("mix_dco2", InternalValue::Numeric(0.0)),
+                    ("vcf_fm", InternalValue::Numeric(fm)),
+                ]),
+            )
+        };
+        assert!(!all_approx_eq(with_fm(0.0), with_fm(50.0), 1e-3));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn max_vcf_fm_stays_finite() {
+        for model in [0, 1] {
+            for (sampling_rate, processing_mode) in [
+                (44100.0, ProcessingMode::Realtime),
+                (48000.0, ProcessingMode::Realtime),
+                (48000.0, ProcessingMode::Offline),
+            ] {
+                let snapshot = generate_snapshot(
+                    &Component {},
+                    48000,
+                    &ProcessingParams {
+                        sampling_rate,
+                        processing_mode,
+                        ..Default::default()
+                    },
+                    &HashMap::from([
+                        ("vcf_model", InternalValue::Enum(model)),
+                        ("vcf_cutoff", InternalValue::Numeric(100.0)),
+                        ("vcf_env", InternalValue::Numeric(100.0)),
+                        ("resonance", InternalValue::Numeric(100.0)),
+                        ("vcf_fm", InternalValue::Numeric(100.0)),
+                    ]),
+                    &get_single_note_events(48000),
+                );
+                assert!(snapshot.iter().all(|x| x.is_finite() && x.abs() < 10.0));
+            }
+        }
+    }
+}
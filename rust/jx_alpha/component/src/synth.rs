@@ -1,7 +1,7 @@
 use std::ops::RangeInclusive;
 
 use conformal_component::{
-    ProcessingEnvironment, Processor,
+    ProcessingEnvironment, ProcessingMode, Processor,
     audio::{BufferMut, channels_mut},
     events::{Data, Event},
     pgrab, pzip,
@@ -40,6 +40,7 @@ pub struct Synth {
     lfo_delay_env: dsp::env::duck::Ar,
     lfo_scratch: Vec<f32>,
     sampling_rate: f32,
+    offline: bool,
 }
 
 impl Synth {
@@ -51,6 +52,7 @@ impl Synth {
             lfo_delay_env: Default::default(),
             lfo_scratch: vec![0f32; env.max_samples_per_process_call],
             sampling_rate: env.sampling_rate,
+            offline: matches!(env.processing_mode, ProcessingMode::Offline),
         }
     }
 }
@@ -139,8 +141,14 @@ impl SynthTrait for Synth {
                     .generate(incr, FromPrimitive::from_u32(shape_int).unwrap());
         }
 
-        self.poly
-            .process(context, &voice::SharedData { lfo: lfo_scratch }, output);
+        self.poly.process(
+            context,
+            &voice::SharedData {
+                lfo: lfo_scratch,
+                offline: self.offline,
+            },
+            output,
+        );
 
         for (channel, hpf) in channels_mut(output).zip(self.hpfs.iter_mut()) {
             hpf.process(Mode::from_u32(hpf_mode).unwrap(), channel);
@@ -6,6 +6,7 @@ use conformal_poly::{EventData, Voice as VoiceTrait, VoiceProcessContext};
 use dsp::{
     env::adsr,
     f32::{exp_approx, exp2_approx, rescale, rescale_clamped, rescale_points},
+    iir::downsampler::Downsampler,
     slew::{self, OnePoleSmoother},
 };
 use itertools::izip;
@@ -14,15 +15,22 @@ use num_traits::FromPrimitive;
 use oscillators::OscillatorSettings;
 
 mod env;
+mod ladder;
 mod oscillators;
 mod vcf;
 
 const KEY_FOLLOW_NOMINAL_PITCH: f32 = 60.0;
+
+// Range of the cutoff modulation from DCO2 at 100% FM.
+const MAX_VCF_FM_SEMITONES: f32 = 48.0;
 const CONTROL_SMOOTHING_TIME_SECONDS: f32 = 0.02;
 
 #[derive(Debug, Clone)]
 pub struct SharedData<'a> {
     pub lfo: &'a [f32],
+
+    // When rendering offline, we run the oscillators and VCF at twice the sampling rate.
+    pub offline: bool,
 }
 
 #[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Default)]
@@ -57,6 +65,13 @@ pub enum DynamicMode {
     VelocityPlusTimbre,
 }
 
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Default)]
+pub enum VcfModel {
+    #[default]
+    Svf,
+    Ladder,
+}
+
 #[derive(FromPrimitive, Copy, Clone, Debug, PartialEq, Default)]
 pub enum VcaEnvSource {
     #[default]
@@ -73,6 +88,14 @@ pub struct Voice {
     sampling_rate: f32,
     oscillators: oscillators::Oscillators,
     vcf: vcf::Vcf,
+    ladder: ladder::Ladder,
+
+    /// The filter we ran last, so we can clear the other one when switching.
+    vcf_model: VcfModel,
+
+    /// Used to decimate the oscillators and VCF when rendering offline.
+    downsampler: Downsampler,
+
     env1: env::Env,
     env2: env::Env,
 
@@ -233,6 +256,44 @@ fn env_params(params: &RawEnvParams) -> env::Params {
     }
 }
 
+struct FilterParams {
+    model: VcfModel,
+
+    /// Cutoff as a MIDI pitch
+    cutoff: f32,
+
+    /// Semitones of cutoff modulation per unit of DCO2 output
+    fm: f32,
+
+    resonance: f32,
+}
+
+impl Voice {
+    /// Runs the oscillators and VCF for a single sample at `sampling_rate`.
+    fn filtered_sample(
+        &mut self,
+        oscillator_settings: &oscillators::Settings,
+        filter_params: &FilterParams,
+        sampling_rate: f32,
+    ) -> f32 {
+        let oscillators::Output { mix, dco2 } = self.oscillators.generate(oscillator_settings);
+        let settings = vcf::Settings {
+            // Note that `increment` keeps the cutoff below nyquist at `sampling_rate`, which
+            // is the oversampled rate when rendering offline, so audio-rate FM can't make
+            // the filters unstable.
+            cutoff_incr: increment(
+                filter_params.cutoff + filter_params.fm * dco2,
+                sampling_rate,
+            ),
+            resonance: filter_params.resonance,
+        };
+        match filter_params.model {
+            VcfModel::Svf => self.vcf.process(mix, &settings),
+            VcfModel::Ladder => self.ladder.process(mix, &settings),
+        }
+    }
+}
+
 impl VoiceTrait for Voice {
     type SharedData<'a> = SharedData<'a>;
 
@@ -243,6 +304,9 @@ impl VoiceTrait for Voice {
             oscillators: oscillators::Oscillators::default(),
             sampling_rate,
             vcf: vcf::Vcf::default(),
+            ladder: ladder::Ladder::default(),
+            vcf_model: VcfModel::default(),
+            downsampler: Downsampler::default(),
             env1: env::Env::default(),
             env2: env::Env::default(),
             level_smoother: OnePoleSmoother::default(),
@@ -325,6 +389,7 @@ impl VoiceTrait for Voice {
             vcf_lfo,
             vcf_env_source_int,
             vcf_dyn_mode_int,
+            vcf_model_int,
             vca_env_source_int,
             vca_dyn_mode_int,
             env1_t1,
@@ -371,6 +436,7 @@ impl VoiceTrait for Voice {
             numeric "vcf_lfo",
             enum "vcf_env_source",
             enum "vcf_dyn_mode",
+            enum "vcf_model",
             enum "vca_env_source",
             enum "vca_dyn_mode",
             numeric "env1_t1",
@@ -401,8 +467,21 @@ impl VoiceTrait for Voice {
         let dco2_range = DcoRange::from_u32(dco2_range_int).unwrap();
         let vcf_env_source = EnvSource::from_u32(vcf_env_source_int).unwrap();
         let vcf_dyn_mode = DynamicMode::from_u32(vcf_dyn_mode_int).unwrap();
+        let vcf_model = VcfModel::from_u32(vcf_model_int).unwrap();
+        if vcf_model != self.vcf_model {
+            match self.vcf_model {
+                VcfModel::Svf => self.vcf.reset(),
+                VcfModel::Ladder => self.ladder.reset(),
+            }
+            self.vcf_model = vcf_model;
+        }
         let vca_env_source = VcaEnvSource::from_u32(vca_env_source_int).unwrap();
         let vca_dyn_mode = DynamicMode::from_u32(vca_dyn_mode_int).unwrap();
+        let oversampled_rate = if shared_data.offline {
+            self.sampling_rate * 2.0
+        } else {
+            self.sampling_rate
+        };
         for (
             (index, sample),
             (
@@ -411,6 +490,7 @@ impl VoiceTrait for Voice {
                 mix_dco2,
                 vcf_cutoff,
                 resonance,
+                vcf_fm,
                 global_pitch_bend,
                 expression_pitch_bend,
                 global_aftertouch,
@@ -427,6 +507,7 @@ impl VoiceTrait for Voice {
                 numeric "mix_dco2",
                 numeric "vcf_cutoff",
                 numeric "resonance",
+                numeric "vcf_fm",
                 global_expression_numeric PitchBend,
                 external_numeric (per_note_pitch_bend),
                 global_expression_numeric Aftertouch,
@@ -508,14 +589,14 @@ impl VoiceTrait for Voice {
                     + dco_adjust(dco1_range, dco1_tune, 0.0)
                     + osc0_env
                     + osc0_lfo_adjust,
-                self.sampling_rate,
+                oversampled_rate,
             );
             let osc1_incr = increment(
                 adjusted_pitch
                     + dco_adjust(dco2_range, dco2_tune, dco2_fine_tune)
                     + osc1_env
                     + osc1_lfo_adjust,
-                self.sampling_rate,
+                oversampled_rate,
             );
             let osc0_gain = volume_to_gain(rescale(mix_dco1, 0.0..=100.0, 0.0..=1.0));
             let osc1_gain = volume_to_gain(
@@ -531,45 +612,41 @@ impl VoiceTrait for Voice {
                             mix_dyn_mode,
                         ),
             );
-            let oscillators_output = self.oscillators.generate(&{
-                oscillators::Settings {
-                    oscillators: [
-                        OscillatorSettings {
-                            increment: osc0_incr,
-                            shape: shape0,
-                            gain: osc0_gain,
-                            pwm_depth: rescale(dco1_pwm_depth, 0.0..=100.0, 0.0..=1.0),
-                            pwm_incr: if oscillators::needs_pwm(shape0) {
-                                get_dco_pwm_incr(dco1_pwm_rate, self.sampling_rate)
-                            } else {
-                                0.0
-                            },
+            let oscillator_settings = oscillators::Settings {
+                oscillators: [
+                    OscillatorSettings {
+                        increment: osc0_incr,
+                        shape: shape0,
+                        gain: osc0_gain,
+                        pwm_depth: rescale(dco1_pwm_depth, 0.0..=100.0, 0.0..=1.0),
+                        pwm_incr: if oscillators::needs_pwm(shape0) {
+                            get_dco_pwm_incr(dco1_pwm_rate, oversampled_rate)
+                        } else {
+                            0.0
                         },
-                        OscillatorSettings {
-                            increment: osc1_incr,
-                            shape: shape1,
-                            gain: osc1_gain,
-                            pwm_depth: rescale(dco2_pwm_depth, 0.0..=100.0, 0.0..=1.0),
-                            pwm_incr: if oscillators::needs_pwm(shape1) {
-                                get_dco_pwm_incr(dco2_pwm_rate, self.sampling_rate)
-                            } else {
-                                0.0
-                            },
-                        },
-                    ],
-                    x_mod: match x_mod {
-                        Dco2XMod::Bit => oscillators::CrossModulation::And,
-                        Dco2XMod::Off | Dco2XMod::Sync => oscillators::CrossModulation::Off,
-                        Dco2XMod::Ring | Dco2XMod::SyncPlusRing => {
-                            oscillators::CrossModulation::Ring
-                        }
                     },
-                    sync: match x_mod {
-                        Dco2XMod::Sync | Dco2XMod::SyncPlusRing => oscillators::Sync::Hard,
-                        _ => oscillators::Sync::Off,
+                    OscillatorSettings {
+                        increment: osc1_incr,
+                        shape: shape1,
+                        gain: osc1_gain,
+                        pwm_depth: rescale(dco2_pwm_depth, 0.0..=100.0, 0.0..=1.0),
+                        pwm_incr: if oscillators::needs_pwm(shape1) {
+                            get_dco_pwm_incr(dco2_pwm_rate, oversampled_rate)
+                        } else {
+                            0.0
+                        },
                     },
-                }
-            });
+                ],
+                x_mod: match x_mod {
+                    Dco2XMod::Bit => oscillators::CrossModulation::And,
+                    Dco2XMod::Off | Dco2XMod::Sync => oscillators::CrossModulation::Off,
+                    Dco2XMod::Ring | Dco2XMod::SyncPlusRing => oscillators::CrossModulation::Ring,
+                },
+                sync: match x_mod {
+                    Dco2XMod::Sync | Dco2XMod::SyncPlusRing => oscillators::Sync::Hard,
+                    _ => oscillators::Sync::Off,
+                },
+            };
 
             let smoothed_raw_vcf_cutoff = self
                 .cutoff_smoother
@@ -593,13 +670,21 @@ impl VoiceTrait for Voice {
                         vcf_dyn_mode,
                     )
                 + rescale(vcf_lfo, 0.0..=100.0, 0.0..=84.0) * lfo;
-            let cutoff_incr = increment(adjusted_vcf_cutoff, self.sampling_rate);
-            let resonance = rescale(resonance, 0.0..=100.0, 0.0..=1.0);
-            let vcf_settings = vcf::Settings {
-                cutoff_incr,
-                resonance,
+            let filter_params = FilterParams {
+                model: vcf_model,
+                cutoff: adjusted_vcf_cutoff,
+                fm: rescale(vcf_fm, 0.0..=100.0, 0.0..=MAX_VCF_FM_SEMITONES),
+                resonance: rescale(resonance, 0.0..=100.0, 0.0..=1.0),
+            };
+            let vcf_output = if shared_data.offline {
+                let a =
+                    self.filtered_sample(&oscillator_settings, &filter_params, oversampled_rate);
+                let b =
+                    self.filtered_sample(&oscillator_settings, &filter_params, oversampled_rate);
+                self.downsampler.process([a, b])
+            } else {
+                self.filtered_sample(&oscillator_settings, &filter_params, oversampled_rate)
             };
-            let vcf_output = self.vcf.process(oscillators_output, &vcf_settings);
             let vca_volume = rescale(
                 self.level_smoother
                     .process(level, self.control_smoothing_coeff),
@@ -627,6 +712,8 @@ impl VoiceTrait for Voice {
         self.pitch = 20.0;
         self.oscillators.reset();
         self.vcf.reset();
+        self.ladder.reset();
+        self.downsampler.reset();
         self.env1.reset();
         self.env2.reset();
         self.level_smoother.reset();
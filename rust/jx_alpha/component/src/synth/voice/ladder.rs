@@ -0,0 +1,186 @@
+use dsp::iir::svf::calc_g;
+
+use super::vcf::Settings;
+
+/// Feedback gain at full resonance. The linear ladder starts to self-oscillate at 4.0,
+/// so we go a bit beyond that to let the saturation settle the oscillation amplitude.
+const MAX_FEEDBACK: f64 = 4.4;
+
+/// How much of the passband gain lost to feedback we add back in.
+const FEEDBACK_COMPENSATION: f64 = 0.5;
+
+/// Scales the signal into the saturating feedback node. Higher values saturate earlier.
+const DRIVE: f64 = 0.5;
+
+/// A nonlinear model of a four-pole OTA ladder filter, like the IR3109.
+///
+/// Each stage is a trapezoidal one-pole lowpass. We solve the feedback loop as if it
+/// were linear, then saturate the input to the first stage. This lets the filter
+/// self-oscillate at full resonance with a bounded amplitude.
+#[derive(Default, Debug, Clone)]
+pub struct Ladder {
+    states: [f64; 4],
+}
+
+impl Ladder {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn process(&mut self, input: f32, settings: &Settings) -> f32 {
+        let g = calc_g(f64::from(settings.cutoff_incr));
+        let stage_gain = g / (1.0 + g);
+        let k = MAX_FEEDBACK * f64::from(settings.resonance);
+        let input = f64::from(input) * (1.0 + FEEDBACK_COMPENSATION * k);
+
+        // Each stage outputs `stage_gain * input + (1 - stage_gain) * state`, so the
+        // output of the whole cascade is `stage_gain^4 * input` plus the stacked state terms.
+        let state_contribution = self.states.iter().fold(0.0, |acc, state| {
+            acc * stage_gain + (1.0 - stage_gain) * state
+        });
+        let cascade_gain = stage_gain.powi(4);
+        let linear_output = (cascade_gain * input + state_contribution) / (1.0 + k * cascade_gain);
+
+        let mut output = (DRIVE * (input - k * linear_output)).tanh() / DRIVE;
+        for state in &mut self.states {
+            let v = stage_gain * (output - *state);
+            output = v + *state;
+            *state = output + v;
+        }
+        output as f32
+    }
+
+    pub fn reset(&mut self) {
+        self.states = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::cast_precision_loss)]
+
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use conformal_component::audio::all_approx_eq;
+    use dsp::test_utils::{estimate_tuning, white_noise, windowed_rfft};
+    use more_asserts::{assert_gt, assert_lt};
+    use snapshots::assert_snapshot;
+
+    fn process_impulse(ladder: &mut Ladder, settings: &Settings, length: usize) -> Vec<f32> {
+        std::iter::once(1.0)
+            .chain(std::iter::repeat(0.0))
+            .take(length)
+            .map(|x| ladder.process(x, settings))
+            .collect()
+    }
+
+    fn rms(data: &[f32]) -> f32 {
+        (data.iter().map(|x| x * x).sum::<f32>() / data.len() as f32).sqrt()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn lowers_high_frequencies() {
+        let mut ladder = Ladder::default();
+        let mut input: Vec<f32> = white_noise(8192).into_iter().map(|x| x * 0.1).collect();
+        let mut processed: Vec<f32> = input
+            .iter()
+            .map(|x| {
+                ladder.process(
+                    *x,
+                    &Settings {
+                        cutoff_incr: 0.05,
+                        resonance: 0.0,
+                    },
+                )
+            })
+            .collect();
+        let spectrum = windowed_rfft(&mut input);
+        let processed_spectrum = windowed_rfft(&mut processed);
+
+        let power_reduction_at_half_nyquist =
+            processed_spectrum[2048].norm_sqr() / spectrum[2048].norm_sqr();
+        assert_lt!(power_reduction_at_half_nyquist, 0.001);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn self_oscillates_at_full_resonance() {
+        let mut ladder = Ladder::default();
+        let settings = Settings {
+            cutoff_incr: 0.02,
+            resonance: 1.0,
+        };
+        let mut output = process_impulse(&mut ladder, &settings, 48000);
+        let tail = &mut output[48000 - 4096..];
+        assert_gt!(rms(tail), 0.1);
+        assert_approx_eq!(estimate_tuning(tail), settings.cutoff_incr, 0.0005);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decays_below_full_resonance() {
+        let mut ladder = Ladder::default();
+        let output = process_impulse(
+            &mut ladder,
+            &Settings {
+                cutoff_incr: 0.02,
+                resonance: 0.8,
+            },
+            48000,
+        );
+        assert_lt!(rms(&output[48000 - 4096..]), 1e-6);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn saturation_bounds_output() {
+        let mut ladder = Ladder::default();
+        let settings = Settings {
+            cutoff_incr: 0.05,
+            resonance: 1.0,
+        };
+        for x in white_noise(48000).into_iter().map(|x| x * 10.0) {
+            assert_lt!(ladder.process(x, &settings).abs(), 10.0);
+        }
+    }
+
+    #[test]
+    fn reset_basics() {
+        let mut ladder = Ladder::default();
+        let settings = Settings {
+            cutoff_incr: 0.10,
+            resonance: 0.5,
+        };
+        let input = white_noise(32);
+        let first: Vec<_> = input
+            .iter()
+            .map(|x| ladder.process(*x, &settings))
+            .collect();
+        ladder.reset();
+        let second: Vec<_> = input
+            .iter()
+            .map(|x| ladder.process(*x, &settings))
+            .collect();
+        assert!(all_approx_eq(first, second, 1e-6));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn sweep_high_res_snapshot() {
+        let mut ladder = Ladder::default();
+        let input = white_noise(48000);
+        let len = input.len() as f32;
+        let processed: Vec<_> = input
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| {
+                ladder.process(
+                    x * 0.3,
+                    &Settings {
+                        cutoff_incr: dsp::f32::lerp(0.01, 0.25, idx as f32 / len),
+                        resonance: 1.0,
+                    },
+                ) * 0.5
+            })
+            .collect();
+        assert_snapshot!("ladder/sweep_high_res", 48000, processed);
+    }
+}
@@ -1,8 +1,8 @@
+use dsp::iir::downsampler::Downsampler;
 use num_derive::FromPrimitive;
 use oscillator::Oscillator;
 
 mod and;
-mod downsampler;
 mod oscillator;
 mod ring;
 
@@ -15,7 +15,18 @@ pub struct Oscillators {
     pwm_lfos: [dsp::sine_lfo::SineLfo; 2],
     ring: ring::Ring,
     and: and::And,
-    downsampler: downsampler::Downsampler,
+    downsampler: Downsampler,
+    dco2_downsampler: Downsampler,
+}
+
+/// The output of the oscillator section for one sample.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Output {
+    /// The mix of both oscillators, including cross modulation.
+    pub mix: f32,
+
+    /// DCO2 on its own, before cross modulation or gain. Useful as a modulation source.
+    pub dco2: f32,
 }
 
 pub use oscillator::needs_pwm;
@@ -88,13 +99,14 @@ impl Oscillators {
         }
         self.ring.reset();
         self.downsampler.reset();
+        self.dco2_downsampler.reset();
         self.and.reset();
         for lfo in &mut self.pwm_lfos {
             lfo.reset();
         }
     }
 
-    fn generate_high_rate(&mut self, settings: &Settings) -> f32 {
+    fn generate_high_rate(&mut self, settings: &Settings) -> Output {
         let [osc0, osc1] = &mut self.oscillators;
         let pwm0_out = if oscillator::needs_pwm(settings.oscillators[0].shape) {
             self.pwm_lfos[0].generate(settings.oscillators[0].pwm_incr)
@@ -113,22 +125,29 @@ impl Oscillators {
             Sync::Off => osc1.generate(osc1_settings),
             Sync::Hard => osc1.generate_with_sync(osc1_settings, osc0, osc0_settings.increment),
         };
-        osc0_out * settings.oscillators[0].gain
-            + settings.oscillators[1].gain
-                * match settings.x_mod {
-                    CrossModulation::Ring => self.ring.process(osc0_out, osc1_out),
-                    CrossModulation::And => {
-                        self.and
-                            .process(osc1_out, osc0.current_phase(), osc0_settings.increment)
-                    }
-                    CrossModulation::Off => osc1_out,
-                }
+        Output {
+            mix: osc0_out * settings.oscillators[0].gain
+                + settings.oscillators[1].gain
+                    * match settings.x_mod {
+                        CrossModulation::Ring => self.ring.process(osc0_out, osc1_out),
+                        CrossModulation::And => self.and.process(
+                            osc1_out,
+                            osc0.current_phase(),
+                            osc0_settings.increment,
+                        ),
+                        CrossModulation::Off => osc1_out,
+                    },
+            dco2: osc1_out,
+        }
     }
 
-    pub fn generate(&mut self, settings: &Settings) -> f32 {
+    pub fn generate(&mut self, settings: &Settings) -> Output {
         let a = self.generate_high_rate(settings);
         let b = self.generate_high_rate(settings);
-        self.downsampler.process([a, b])
+        Output {
+            mix: self.downsampler.process([a.mix, b.mix]),
+            dco2: self.dco2_downsampler.process([a.dco2, b.dco2]),
+        }
     }
 }
 
@@ -151,7 +170,7 @@ mod tests {
 
     fn snapshot_for_settings(settings: &Settings, length: usize) -> Vec<f32> {
         let mut oscillators = Oscillators::default();
-        std::iter::repeat_with(move || oscillators.generate(settings) * 0.5)
+        std::iter::repeat_with(move || oscillators.generate(settings).mix * 0.5)
             .take(length)
             .collect()
     }
@@ -494,11 +513,11 @@ mod tests {
             ..Default::default()
         };
         let length = 1024;
-        let initial = std::iter::repeat_with(|| oscillators.generate(&settings))
+        let initial = std::iter::repeat_with(|| oscillators.generate(&settings).mix)
             .take(length)
             .collect::<Vec<_>>();
         oscillators.reset();
-        let reset = std::iter::repeat_with(|| oscillators.generate(&settings))
+        let reset = std::iter::repeat_with(|| oscillators.generate(&settings).mix)
             .take(length)
             .collect::<Vec<_>>();
         assert!(all_approx_eq(initial, reset, 1e-6));
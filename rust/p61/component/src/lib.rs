@@ -15,7 +15,7 @@ const fn percentage(default: f32) -> TypeSpecificInfoRef<'static, &'static str>
     }
 }
 
-static PARAMETERS: [InfoRef<'static, &'static str>; 27] = [
+static PARAMETERS: [InfoRef<'static, &'static str>; 29] = [
     InfoRef {
         title: "DCO1 Shape",
         short_title: "DCO1Shape",
@@ -98,6 +98,16 @@ static PARAMETERS: [InfoRef<'static, &'static str>; 27] = [
         flags: Flags { automatable: true },
         type_specific: percentage(0.0),
     },
+    InfoRef {
+        title: "VCF Mode",
+        short_title: "VCFMode",
+        unique_id: "vcf_mode",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["LP12", "LP24", "HP", "BP", "Notch"],
+        },
+    },
     InfoRef {
         title: "VCF Key Tracking",
         short_title: "VCFKey",
@@ -243,6 +253,16 @@ static PARAMETERS: [InfoRef<'static, &'static str>; 27] = [
         flags: Flags { automatable: true },
         type_specific: percentage(0.0),
     },
+    InfoRef {
+        title: "Oversampling",
+        short_title: "Oversampling",
+        unique_id: "oversampling",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["Off", "2x", "4x"],
+        },
+    },
 ];
 
 impl ComponentT for Component {
@@ -269,7 +289,14 @@ mod tests {
             generate_snapshot_with_reset, get_single_note_events,
         },
     };
-    use conformal_component::{ProcessingMode, audio::all_approx_eq, parameters::InternalValue};
+    use conformal_component::{
+        ProcessingMode,
+        audio::all_approx_eq,
+        events::{Data, Event, NoteData, NoteID},
+        parameters::InternalValue,
+    };
+    use dsp::{osc_utils::increment, test_utils::estimate_aliasing};
+    use more_asserts::assert_lt;
     use snapshots::assert_snapshot;
     fn snapshot_param_overrides() -> HashMap<&'static str, InternalValue> {
         HashMap::from([
@@ -373,4 +400,64 @@ mod tests {
         let snapshot = generate_basic_snapshot(component, 48000, &HashMap::new());
         assert_snapshot!("defaults", 48000, snapshot);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_offline() {
+        let component = &Component {};
+        let snapshot = generate_snapshot(
+            component,
+            48000,
+            &ProcessingParams {
+                processing_mode: ProcessingMode::Offline,
+                ..Default::default()
+            },
+            &snapshot_param_overrides(),
+            &get_single_note_events(48000),
+        );
+        assert_snapshot!("offline", 48000, snapshot);
+    }
+
+    fn high_note_aliasing(processing_mode: ProcessingMode) -> f32 {
+        let pitch = 100;
+        let events = [Event {
+            sample_offset: 0,
+            data: Data::NoteOn {
+                data: NoteData {
+                    id: NoteID::from_pitch(pitch),
+                    pitch,
+                    velocity: 1.0,
+                    tuning: 0.0,
+                },
+            },
+        }];
+        let mut output = generate_snapshot(
+            &Component {},
+            16384,
+            &ProcessingParams {
+                processing_mode,
+                ..Default::default()
+            },
+            &HashMap::from([
+                ("vcf_cutoff", InternalValue::Numeric(100.0)),
+                ("vcf_env", InternalValue::Numeric(0.0)),
+                ("sustain", InternalValue::Numeric(100.0)),
+            ]),
+            &events.into_iter(),
+        );
+        // Skip the attack, and measure slightly below the fundamental so the
+        // window's main lobe isn't counted as aliasing.
+        estimate_aliasing(
+            &mut output[8192..],
+            0.95 * increment(f32::from(pitch), 48000.0),
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn offline_rendering_reduces_aliasing() {
+        let realtime = high_note_aliasing(ProcessingMode::Realtime);
+        let offline = high_note_aliasing(ProcessingMode::Offline);
+        assert_lt!(offline, realtime - 6.0);
+    }
 }
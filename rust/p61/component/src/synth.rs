@@ -1,5 +1,5 @@
 use conformal_component::{
-    ProcessingEnvironment, Processor,
+    ProcessingEnvironment, ProcessingMode, Processor,
     audio::BufferMut,
     events::{Data, Event},
     parameters::{self},
@@ -27,6 +27,8 @@ pub struct Synth {
     wheel_scratch: Vec<f32>,
 
     sampling_rate: f32,
+
+    offline: bool,
 }
 
 impl Synth {
@@ -41,6 +43,8 @@ impl Synth {
             wheel_scratch: vec![0f32; env.max_samples_per_process_call],
 
             sampling_rate: env.sampling_rate,
+
+            offline: matches!(env.processing_mode, ProcessingMode::Offline),
         }
     }
 }
@@ -149,6 +153,7 @@ impl SynthT for Synth {
             &SharedData {
                 mg_data: mg_scratch,
                 wheel_data: wheel_scratch,
+                offline: self.offline,
             },
             output,
         );
@@ -5,6 +5,7 @@ use dsp::{
 };
 
 use conformal_component::{pzip, synth::NumericPerNoteExpression};
+use dsp::iir::downsampler::Downsampler;
 
 mod dco1;
 mod dco2;
@@ -37,6 +38,10 @@ pub struct Voice {
 
     vca: vca::Vca,
     vcf: vcf::Vcf,
+
+    /// Decimation stages for the oversampled path. The first stage
+    /// goes from 2x to the base rate, the second from 4x to 2x.
+    downsamplers: [Downsampler; 2],
 }
 
 #[derive(FromPrimitive, Copy, Clone, Debug, PartialEq)]
@@ -75,6 +80,13 @@ pub(crate) enum VcaMode {
     Envelope,
 }
 
+#[derive(FromPrimitive, Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Oversampling {
+    Off,
+    X2,
+    X4,
+}
+
 struct OscSectionParams {
     dco1_shape: Dco1Shape,
     dco1_width: f32,
@@ -90,6 +102,7 @@ struct Params {
 
     vcf_cutoff: f32,
     vcf_resonance: f32,
+    vcf_mode: vcf::Mode,
     vcf_tracking: f32,
     vcf_env: f32,
     vcf_velocity: f32,
@@ -117,8 +130,11 @@ struct Params {
 
     per_note_pitch_bend: f32,
     per_note_timbre: f32,
+
+    oversampling: Oversampling,
 }
 
+#[allow(clippy::too_many_lines)]
 fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item = Params> {
     let pitch_bend = context.per_note_expression(NumericPerNoteExpression::PitchBend);
     let timbre = context.per_note_expression(NumericPerNoteExpression::Timbre);
@@ -131,6 +147,7 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
                  enum "dco2_interval",
                  numeric "vcf_cutoff",
                  numeric "vcf_resonance",
+                 enum "vcf_mode",
                  numeric "vcf_tracking",
                  numeric "vcf_env",
                  numeric "vcf_velocity",
@@ -150,7 +167,8 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
                  global_expression_numeric Timbre,
                  numeric "timbre_vcf",
                  external_numeric (pitch_bend),
-                 external_numeric (timbre)
+                 external_numeric (timbre),
+                 enum "oversampling"
     ])
     .map(
         |(
@@ -163,6 +181,7 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
             dco2_interval,
             vcf_cutoff,
             vcf_resonance,
+            vcf_mode,
             vcf_tracking,
             vcf_env,
             vcf_velocity,
@@ -183,6 +202,7 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
             timbre_vcf,
             per_note_pitch_bend,
             per_note_timbre,
+            oversampling,
         )| Params {
             osc: OscSectionParams {
                 dco1_shape: FromPrimitive::from_u32(dco1_shape).unwrap(),
@@ -195,6 +215,7 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
             },
             vcf_cutoff,
             vcf_resonance,
+            vcf_mode: FromPrimitive::from_u32(vcf_mode).unwrap(),
             vcf_tracking,
             vcf_env,
             vcf_velocity,
@@ -221,6 +242,8 @@ fn per_sample_params(context: &impl VoiceProcessContext) -> impl Iterator<Item =
 
             per_note_pitch_bend,
             per_note_timbre,
+
+            oversampling: FromPrimitive::from_u32(oversampling).unwrap(),
         },
     )
 }
@@ -231,6 +254,9 @@ pub struct SharedData<'a> {
 
     // Mod-wheel modulation data
     pub wheel_data: &'a [f32],
+
+    // When rendering offline, we always use the highest oversampling factor.
+    pub offline: bool,
 }
 
 impl Voice {
@@ -247,6 +273,7 @@ impl Voice {
         }: &OscSectionParams,
         midi_number: f32,
         mg: f32,
+        sampling_rate: f32,
     ) -> f32 {
         let dco1_incr = increment(
             match dco1_octave {
@@ -254,7 +281,7 @@ impl Voice {
                 Octave::Medium => 0.0,
                 Octave::High => 12.0,
             } + midi_number,
-            self.sampling_rate,
+            sampling_rate,
         );
         let dco1 = match dco1_shape {
             Dco1Shape::Saw => self.dco1.generate(dco1_incr, midi_number, dco1::Shape::Saw),
@@ -294,7 +321,7 @@ impl Voice {
                     + dco2_interval_offset
                     + midi_number
                     + (dco2_detune_cents) / 100.0,
-                self.sampling_rate,
+                sampling_rate,
             )
         };
         // Sound quality opportunity - currently we instantly turn
@@ -313,12 +340,70 @@ impl Voice {
             _ => 0.707 * (dco1 + dco2),
         }
     }
+
+    /// Runs the oscillators and VCF for one sample at `factor` times the base rate.
+    ///
+    /// Note that `filter.cutoff_incr` is relative to the base rate.
+    fn filtered_sample(
+        &mut self,
+        osc_params: &OscSectionParams,
+        osc_midi_number: f32,
+        mg: f32,
+        filter: &FilterParams,
+        factor: u8,
+    ) -> f32 {
+        let factor = f32::from(factor);
+        let osc =
+            self.osc_section_sample(osc_params, osc_midi_number, mg, self.sampling_rate * factor);
+        self.vcf.process(
+            osc,
+            filter.cutoff_incr / factor,
+            filter.resonance,
+            filter.mode,
+        )
+    }
+
+    /// Runs the oscillators and VCF for one base-rate sample, oversampling
+    /// to reduce aliasing from the VCF nonlinearity.
+    fn oversampled_sample(
+        &mut self,
+        oversampling: Oversampling,
+        osc_params: &OscSectionParams,
+        osc_midi_number: f32,
+        mg: f32,
+        filter: &FilterParams,
+    ) -> f32 {
+        let run = |voice: &mut Self, factor| {
+            voice.filtered_sample(osc_params, osc_midi_number, mg, filter, factor)
+        };
+        match oversampling {
+            Oversampling::Off => run(self, 1),
+            Oversampling::X2 => {
+                let x = [run(self, 2), run(self, 2)];
+                self.downsamplers[0].process(x)
+            }
+            Oversampling::X4 => {
+                let a = [run(self, 4), run(self, 4)];
+                let a = self.downsamplers[1].process(a);
+                let b = [run(self, 4), run(self, 4)];
+                let b = self.downsamplers[1].process(b);
+                self.downsamplers[0].process([a, b])
+            }
+        }
+    }
 }
 
 const PITCH_BEND_WIDTH: f32 = 2.0;
 const MAX_WHEEL_DEPTH: f32 = 12.0;
 const MAX_TIMBRE_DEPTH: f32 = 60.0;
 
+struct FilterParams {
+    /// Cutoff increment relative to the base sampling rate
+    cutoff_incr: f32,
+    resonance: f32,
+    mode: vcf::Mode,
+}
+
 struct VcfIncrParams {
     midi_number: f32,
     velocity: f32,
@@ -407,6 +492,7 @@ impl VoiceT for Voice {
             ),
             vca: vca::Vca::new(sampling_rate),
             vcf: vcf::Vcf::new(),
+            downsamplers: Default::default(),
         }
     }
 
@@ -456,15 +542,21 @@ impl VoiceT for Voice {
             let osc_midi_number =
                 lerp(0.0, 12.0, params.mg_pitch * 0.01) * mg + pitch_bend + midi_number + osc_wheel;
 
-            let osc = self.osc_section_sample(&params.osc, osc_midi_number, *mg);
-
             let env = self.adsr.process(&coeffs);
             let gate = self.gate.process(&self.gate_coeffs);
 
-            *sample = self.vca.process(
-                self.vcf.process(
-                    osc,
-                    vcf_incr(VcfIncrParams {
+            let oversampling = if shared_data.offline {
+                Oversampling::X4
+            } else {
+                params.oversampling
+            };
+            let filtered = self.oversampled_sample(
+                oversampling,
+                &params.osc,
+                osc_midi_number,
+                *mg,
+                &FilterParams {
+                    cutoff_incr: vcf_incr(VcfIncrParams {
                         midi_number,
                         velocity,
                         env,
@@ -483,8 +575,17 @@ impl VoiceT for Voice {
                         sampling_rate: self.sampling_rate,
                     })
                     .clamp(0.0, 0.4),
-                    exp2_approx(rescale(params.vcf_resonance, 0.0..=100.0, -0.5f32..=3f32)),
-                ),
+                    resonance: exp2_approx(rescale(
+                        params.vcf_resonance,
+                        0.0..=100.0,
+                        -0.5f32..=3f32,
+                    )),
+                    mode: params.vcf_mode,
+                },
+            );
+
+            *sample = self.vca.process(
+                filtered,
                 params.vca_level
                     * 0.01
                     * lerp(1.0, velocity, params.vca_velocity * 0.01)
@@ -502,6 +603,9 @@ impl VoiceT for Voice {
         self.adsr.reset();
         self.vca.reset();
         self.vcf.reset();
+        for downsampler in &mut self.downsamplers {
+            downsampler.reset();
+        }
     }
 
     fn handle_event(&mut self, event: &EventData) {
@@ -543,10 +647,15 @@ mod tests {
         },
     };
     use conformal_poly::{Event, EventData, Voice as VoiceT, VoiceProcessContext};
+    use dsp::test_utils::estimate_aliasing_gen;
+    use more_asserts::assert_lt;
     use snapshots::assert_snapshot;
     use std::collections::HashMap;
 
-    use super::{Dco2Shape, SharedData, Voice};
+    use super::{
+        Dco1Shape, Dco2Interval, Dco2Shape, FilterParams, Octave, OscSectionParams, Oversampling,
+        SharedData, Voice, dco2, vcf,
+    };
 
     fn get_silent_mg(len: usize) -> Vec<f32> {
         vec![0f32; len]
@@ -565,6 +674,7 @@ mod tests {
         SharedData {
             mg_data: &mg,
             wheel_data: &wheel_mg,
+            offline: false,
         }
     }
 
@@ -808,4 +918,51 @@ mod tests {
             )
         );
     }
+
+    fn aliasing_for_oversampling(oversampling: Oversampling) -> f32 {
+        let mut voice = Voice::new(0, 4096, 48000.0);
+        let osc_params = OscSectionParams {
+            dco1_shape: Dco1Shape::Saw,
+            dco1_width: 0.0,
+            dco1_octave: Octave::Medium,
+            dco2_shape: Dco2Shape::Off,
+            dco2_octave: dco2::Octave::Medium,
+            dco2_detune: 0.0,
+            dco2_interval: Dco2Interval::Unison,
+        };
+        let midi_number = 100.0;
+        let mut generate = || {
+            voice.oversampled_sample(
+                oversampling,
+                &osc_params,
+                midi_number,
+                0.0,
+                &FilterParams {
+                    cutoff_incr: 0.3,
+                    resonance: 8.0,
+                    mode: vcf::Mode::LowPass12,
+                },
+            )
+        };
+        // Let the filter settle so we don't measure the start-up transient.
+        for _ in 0..8192 {
+            generate();
+        }
+        // We measure a bit below the fundamental so that the window's main lobe
+        // around the fundamental isn't counted as aliasing.
+        estimate_aliasing_gen(
+            generate,
+            0.95 * dsp::osc_utils::increment(midi_number, 48000.0),
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn oversampling_reduces_aliasing() {
+        let off = aliasing_for_oversampling(Oversampling::Off);
+        let x2 = aliasing_for_oversampling(Oversampling::X2);
+        let x4 = aliasing_for_oversampling(Oversampling::X4);
+        assert_lt!(x2, off - 12.0);
+        assert_lt!(x4, x2);
+    }
 }
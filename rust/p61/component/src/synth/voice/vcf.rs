@@ -4,9 +4,26 @@ struct State {
     s1: f64,
 }
 
+use num_derive::FromPrimitive;
+
+/// Which response of the filter we output.
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    LowPass12,
+    LowPass24,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
 #[derive(Debug)]
 pub struct Vcf {
     state: State,
+
+    /// State of the extra linear stage used for the 24dB lowpass.
+    post_state: State,
+
     k: f64,
 
     /// Nonlinearity level
@@ -53,13 +70,12 @@ fn update_state(ds0: f64, out: f64, state: &State, c: &Coeffs) -> State {
     State { s0, s1 }
 }
 
-fn update_linear(x: f32, state: &State, c: &Coeffs) -> (f64, State) {
-    let x = f64::from(x);
+/// Returns the output and the input to the s0 integrator
+fn update_linear(x: f64, state: &State, c: &Coeffs) -> (f64, f64) {
     let Coeffs { g, k, two_r, .. } = c;
     let denom = g * g * (two_r * k - 2.0 * k - 1.0) - two_r * g - 1.0;
     let out = (-state.s1 - g * (state.s0 + g * x)) / denom;
-    let ds0 = get_ds0(x, out, c);
-    (out, update_state(ds0, out, state, c))
+    (out, get_ds0(x, out, c))
 }
 
 /// Solve a * x^2 + b * x + c = 0
@@ -134,16 +150,39 @@ fn get_saturated_out(x: f64, state: &State, coeffs: &Coeffs) -> (f64, f64) {
     (0.0, saturate_ds0(get_ds0(x, 0.0, coeffs), coeffs.v))
 }
 
-fn update_saturated(x: f32, state: &State, coeffs: &Coeffs) -> (f64, State) {
-    let x = f64::from(x);
-    let (out, ds0) = get_saturated_out(x, state, coeffs);
-    (out, update_state(ds0, out, state, coeffs))
+/// Gain applied to each mode to keep levels even as resonance changes.
+///
+/// We estimate the power each response passes from white noise, and scale it back to
+/// the power at the butterworth resonance. The resonant peak contributes power in
+/// proportion to `1 / two_r`, scaled by how wide the region around the cutoff is after
+/// frequency warping. The other terms are the passbands - for the lowpass modes and
+/// the notch these are fit to measurements of the nonlinear filter, since the
+/// resonance shift adds some power of its own.
+///
+/// The bandpass is additionally normalized to unity gain at the cutoff.
+fn resonance_compensation(mode: Mode, two_r: f64, cutoff_incr: f32) -> f64 {
+    let incr = f64::from(cutoff_incr).clamp(1e-6, 0.48);
+    let width = (std::f64::consts::TAU * incr).sin() * 0.5;
+    let power = |two_r: f64| match mode {
+        Mode::LowPass12 => width * (0.5 + 1.0 / two_r),
+        Mode::LowPass24 => width * (2.0 + 1.0 / two_r),
+        Mode::HighPass => 1.0 - 2.0 * incr + width / two_r,
+        Mode::BandPass => width / two_r,
+        Mode::Notch => 1.0 + width / (8.0 * two_r),
+    };
+    let compensation = (power(std::f64::consts::SQRT_2) / power(two_r)).sqrt();
+    if mode == Mode::BandPass {
+        compensation * std::f64::consts::SQRT_2
+    } else {
+        compensation
+    }
 }
 
 impl Vcf {
     pub fn new() -> Self {
         Self {
             state: State::default(),
+            post_state: State::default(),
             k: 0.3,
             v: 0.2,
         }
@@ -156,6 +195,7 @@ impl Vcf {
     fn new_linear_with_tuned_resonance() -> Self {
         Self {
             state: State::default(),
+            post_state: State::default(),
             k: 0.0,
             v: 0.0,
         }
@@ -163,9 +203,10 @@ impl Vcf {
 
     pub fn reset(&mut self) {
         self.state = State::default();
+        self.post_state = State::default();
     }
 
-    pub fn process(&mut self, input: f32, cutoff_incr: f32, resonance: f32) -> f32 {
+    pub fn process(&mut self, input: f32, cutoff_incr: f32, resonance: f32, mode: Mode) -> f32 {
         let two_r = calc_two_r(resonance);
         let g = calc_g(cutoff_incr);
         let coeffs = Coeffs {
@@ -174,12 +215,37 @@ impl Vcf {
             g,
             v: self.v,
         };
-        let (out, state) = if self.v > 1e-10 {
-            update_saturated(input, &self.state, &coeffs)
+        let x = f64::from(input);
+        let (low, ds0) = if self.v > 1e-10 {
+            get_saturated_out(x, &self.state, &coeffs)
         } else {
-            update_linear(input, &self.state, &coeffs)
+            update_linear(x, &self.state, &coeffs)
         };
-        self.state = state;
+
+        // The band output is the input to the s1 integrator, and the high output is
+        // whatever remains of the s0 integrator input once the band feedback is removed.
+        let band = self.state.s0 + g * ds0 - two_r * low;
+        let high = ds0 - two_r * band;
+        self.state = update_state(ds0, low, &self.state, &coeffs);
+
+        let out = match mode {
+            Mode::LowPass12 => low,
+            Mode::LowPass24 => {
+                // Cascade into a linear, unresonant stage at the same cutoff.
+                let post_coeffs = Coeffs {
+                    k: 0.0,
+                    two_r: std::f64::consts::SQRT_2,
+                    g,
+                    v: 0.0,
+                };
+                let (out, ds0) = update_linear(low, &self.post_state, &post_coeffs);
+                self.post_state = update_state(ds0, out, &self.post_state, &post_coeffs);
+                out
+            }
+            Mode::HighPass => high,
+            Mode::BandPass => band,
+            Mode::Notch => low + high,
+        } * resonance_compensation(mode, two_r, cutoff_incr);
 
         #[allow(clippy::cast_possible_truncation)]
         let ret = out as f32;
@@ -191,7 +257,7 @@ impl Vcf {
 
 #[cfg(test)]
 mod tests {
-    use super::Vcf;
+    use super::{Mode, Vcf};
     use assert_approx_eq::assert_approx_eq;
     use dsp::f32::exp2_approx;
     use dsp::test_utils::estimate_tuning;
@@ -207,12 +273,12 @@ mod tests {
         let mut initial = white_noise(100);
         let mut initial_clone = initial.clone();
         for sample in initial.iter_mut() {
-            *sample = vcf.process(*sample, 0.1, 0.707);
+            *sample = vcf.process(*sample, 0.1, 0.707, Mode::LowPass12);
         }
         let processed = initial;
         vcf.reset();
         for sample in initial_clone.iter_mut() {
-            *sample = vcf.process(*sample, 0.1, 0.707);
+            *sample = vcf.process(*sample, 0.1, 0.707, Mode::LowPass12);
         }
         let after_reset = initial_clone;
         for (a, b) in processed.iter().zip(after_reset.iter()) {
@@ -236,7 +302,7 @@ mod tests {
         let mut input = white_noise(4096);
         let mut processed = input.clone();
         for sample in processed.iter_mut() {
-            *sample = vcf.process(*sample, cutoff_bin as f32 / 4096.0, 0.707);
+            *sample = vcf.process(*sample, cutoff_bin as f32 / 4096.0, 0.707, Mode::LowPass12);
         }
         let spectrum = windowed_rfft(&mut input);
         let processed_spectrum = windowed_rfft(&mut processed);
@@ -273,6 +339,82 @@ mod tests {
         // Note that higher than this frequency warping becomes an issue
     }
 
+    fn response_db(mode: Mode, cutoff_bin: u16, bin: usize) -> f32 {
+        let mut vcf = Vcf::new_linear_with_tuned_resonance();
+        let mut input = white_noise(4096);
+        let mut processed = input.clone();
+        for sample in &mut processed {
+            *sample = vcf.process(*sample, f32::from(cutoff_bin) / 4096.0, 0.707, mode);
+        }
+        let spectrum = windowed_rfft(&mut input);
+        let processed_spectrum = windowed_rfft(&mut processed);
+        10.0 * (processed_spectrum[bin].norm_sqr() / spectrum[bin].norm_sqr()).log10()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn lowpass_24_has_steeper_slope() {
+        // Two octaves of 24dB per octave
+        assert_approx_eq!(response_db(Mode::LowPass24, 100, 400), -48.0, 4.0);
+        assert_approx_eq!(response_db(Mode::LowPass24, 100, 50), 0.0, 3.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn acts_as_highpass() {
+        assert_approx_eq!(response_db(Mode::HighPass, 100, 25), -24.0, 3.0);
+        assert_approx_eq!(response_db(Mode::HighPass, 100, 400), 0.0, 3.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn acts_as_bandpass() {
+        // The bandpass is compensated to have unity gain at the cutoff
+        assert_approx_eq!(response_db(Mode::BandPass, 100, 100), 0.0, 1.0);
+        assert_approx_eq!(response_db(Mode::BandPass, 100, 25), -9.0, 3.0);
+        assert_approx_eq!(response_db(Mode::BandPass, 100, 400), -9.0, 3.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn acts_as_notch() {
+        assert!(response_db(Mode::Notch, 100, 100) < -20.0);
+        assert_approx_eq!(response_db(Mode::Notch, 100, 25), 0.0, 3.0);
+        assert_approx_eq!(response_db(Mode::Notch, 100, 400), 0.0, 3.0);
+    }
+
+    /// Level of white noise through the production filter, relative to the input in dB.
+    fn noise_level_db(mode: Mode, cutoff_hz: f32, resonance: f32) -> f64 {
+        let mut vcf = Vcf::new();
+        let input: Vec<_> = white_noise(48000).iter().map(|x| x * 0.1).collect();
+        let processed: Vec<_> = input
+            .iter()
+            .map(|x| vcf.process(*x, cutoff_hz / 48000.0, resonance, mode))
+            .collect();
+        let energy = |x: &[f32]| x.iter().map(|x| f64::from(x * x)).sum::<f64>();
+        10.0 * (energy(&processed) / energy(&input)).log10()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn resonance_keeps_level() {
+        for mode in [
+            Mode::LowPass12,
+            Mode::LowPass24,
+            Mode::HighPass,
+            Mode::BandPass,
+            Mode::Notch,
+        ] {
+            for cutoff_hz in [200.0, 1000.0, 5000.0] {
+                let reference = noise_level_db(mode, cutoff_hz, 0.707);
+                for resonance in [5.0, 20.0, 100.0] {
+                    let level = noise_level_db(mode, cutoff_hz, resonance);
+                    assert_approx_eq!(level, reference, 3.0);
+                }
+            }
+        }
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn resonance_tuning() {
@@ -281,7 +423,7 @@ mod tests {
         let increment = 482.5 / 44100.0;
 
         for sample in processed.iter_mut() {
-            *sample = vcf.process(*sample, increment, 100.0);
+            *sample = vcf.process(*sample, increment, 100.0, Mode::LowPass12);
         }
         assert_approx_eq!(estimate_tuning(&mut processed), increment, 1e-3);
     }
@@ -301,11 +443,56 @@ mod tests {
                     -7.0..=0.0,
                 )),
                 10.0,
+                Mode::LowPass12,
             ) / 10.0;
         }
         assert_snapshot!("vcf/sweep", 48000, processed);
     }
 
+    #[allow(clippy::cast_precision_loss)]
+    fn mode_sweep(mode: Mode) -> Vec<f32> {
+        let mut vcf = Vcf::new();
+        let num_samples = 48000;
+        let mut processed = white_noise(num_samples);
+        for (index, sample) in processed.iter_mut().enumerate() {
+            *sample = vcf.process(
+                *sample,
+                0.5 * exp2_approx(rescale(
+                    index as f32,
+                    0.0..=(num_samples as f32),
+                    -7.0..=0.0,
+                )),
+                10.0,
+                mode,
+            ) / 10.0;
+        }
+        processed
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn lowpass_24_sweep_snapshot() {
+        assert_snapshot!("vcf/sweep_lowpass_24", 48000, mode_sweep(Mode::LowPass24));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn highpass_sweep_snapshot() {
+        assert_snapshot!("vcf/sweep_highpass", 48000, mode_sweep(Mode::HighPass));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn bandpass_sweep_snapshot() {
+        assert_snapshot!("vcf/sweep_bandpass", 48000, mode_sweep(Mode::BandPass));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn notch_sweep_snapshot() {
+        assert_snapshot!("vcf/sweep_notch", 48000, mode_sweep(Mode::Notch));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn linear_sweep_snapshot() {
@@ -318,6 +505,7 @@ mod tests {
                 *sample,
                 rescale(index as f32, 0.0..=(num_samples as f32), 0.0..=0.5),
                 10.0,
+                Mode::LowPass12,
             ) / 10.0;
         }
         assert_snapshot!("vcf/linear_sweep", 48000, processed);
@@ -6,6 +6,7 @@ use dsp::iir::svf::{Input, RawParams, Svf, calc_g};
 
 #[derive(Debug, Clone)]
 pub struct AntiAliasingFilter {
+    sampling_rate: f32,
     g: Option<f64>,
 
     a: Svf,
@@ -15,18 +16,25 @@ pub struct AntiAliasingFilter {
     b_damping: f64,
 }
 
-const CUTOFF: f32 = 10_000.;
+/// The cutoff for a 256 stage BBD at the default delay.
+pub const DEFAULT_CUTOFF: f32 = 10_000.;
+
+fn calc_g_for_cutoff(sampling_rate: f32, cutoff: f32) -> Option<f64> {
+    if sampling_rate > DEFAULT_CUTOFF * 2.2 {
+        // Higher cutoffs can't be represented accurately, and would be inaudible anyways.
+        let incr = cutoff.min(sampling_rate / 2.2) / sampling_rate;
+        Some(calc_g(f64::from(incr)))
+    } else {
+        None
+    }
+}
 
 impl AntiAliasingFilter {
     pub fn new(sampling_rate: f32) -> Self {
         // We use a simple butterworth filter
         Self {
-            g: if sampling_rate > CUTOFF * 2.2 {
-                let incr = CUTOFF / sampling_rate;
-                Some(calc_g(f64::from(incr)))
-            } else {
-                None
-            },
+            sampling_rate,
+            g: calc_g_for_cutoff(sampling_rate, DEFAULT_CUTOFF),
             a: Default::default(),
             a_damping: 2. * (std::f64::consts::TAU / 4. * (3. / 4.)).cos(),
             b: Default::default(),
@@ -34,6 +42,11 @@ impl AntiAliasingFilter {
         }
     }
 
+    /// Real devices set the cutoff based on the clock rate of the BBD.
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.g = calc_g_for_cutoff(self.sampling_rate, cutoff);
+    }
+
     pub fn process<'a, I: IntoIterator<Item = f32> + 'a>(
         &'a mut self,
         input: I,
@@ -0,0 +1,119 @@
+//! Models the noise that vintage BBD choruses add to the signal: hiss from the
+//! BBD itself, and bleed from the clock that drives it.
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+const SEED: u64 = 369;
+
+// Levels at full vintage, for a 256 stage device.
+const HISS_LEVEL: f32 = 0.002;
+const CLOCK_LEVEL: f32 = 0.001;
+
+/// The number of stages the levels were tuned for.
+const REFERENCE_STAGES: f32 = 256.;
+
+#[derive(Debug, Clone)]
+pub struct Hiss {
+    rng: Xoshiro256PlusPlus,
+    level: f32,
+}
+
+impl Default for Hiss {
+    fn default() -> Self {
+        Self {
+            rng: Xoshiro256PlusPlus::seed_from_u64(SEED),
+            level: 0.,
+        }
+    }
+}
+
+impl Hiss {
+    /// Each stage adds independent noise, so longer devices are noisier.
+    ///
+    /// `vintage` is between 0 and 1.
+    pub fn set_level(&mut self, vintage: f32, stages: f32) {
+        self.level = vintage * HISS_LEVEL * (stages / REFERENCE_STAGES).sqrt();
+    }
+
+    pub fn process(&mut self) -> f32 {
+        if self.level == 0. {
+            return 0.;
+        }
+        self.rng.gen_range(-1.0..=1.0) * self.level
+    }
+
+    pub fn reset(&mut self) {
+        self.rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+    }
+}
+
+/// The clock of a BBD runs far above nyquist, so whatever leaks past the
+/// reconstruction filter aliases down into the audible range. As the LFO
+/// sweeps the clock rate, the aliased tone sweeps with it.
+#[derive(Debug, Clone, Default)]
+pub struct ClockBleed {
+    phase: f32,
+    level: f32,
+}
+
+impl ClockBleed {
+    /// `vintage` is between 0 and 1.
+    pub fn set_level(&mut self, vintage: f32) {
+        self.level = vintage * CLOCK_LEVEL;
+    }
+
+    /// `incr` is the clock rate in cycles per sample, which will usually be
+    /// greater than one half.
+    pub fn process(&mut self, incr: f32) -> f32 {
+        if self.level == 0. {
+            return 0.;
+        }
+        self.phase = (self.phase + incr).fract();
+        (self.phase * std::f32::consts::TAU).sin() * self.level
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_without_vintage() {
+        let mut hiss = Hiss::default();
+        let mut clock = ClockBleed::default();
+        hiss.set_level(0., 256.);
+        clock.set_level(0.);
+        for _ in 0..100 {
+            assert!(hiss.process().abs() < 1e-9);
+            assert!(clock.process(0.7).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn more_stages_are_noisier() {
+        let energy = |stages: f32| {
+            let mut hiss = Hiss::default();
+            hiss.set_level(1., stages);
+            (0..1000).map(|_| hiss.process().powi(2)).sum::<f32>()
+        };
+        assert!(energy(1024.) > energy(256.) * 3.);
+    }
+
+    #[test]
+    fn clock_aliases() {
+        // A clock at 0.75 cycles per sample aliases down to 0.25 cycles per sample.
+        let mut clock = ClockBleed::default();
+        clock.set_level(1.);
+        let output = (0..8).map(|_| clock.process(0.75)).collect::<Vec<_>>();
+        for (a, b) in output.iter().zip(output.iter().skip(4)) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!((output[0] + output[2]).abs() < 1e-4);
+        assert!(output[0].abs() > CLOCK_LEVEL * 0.9);
+    }
+}
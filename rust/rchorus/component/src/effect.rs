@@ -1,16 +1,19 @@
 use std::array;
 
+use crate::anti_aliasing_filter::{self, AntiAliasingFilter};
+use crate::bbd_noise::{ClockBleed, Hiss};
 use crate::compander::{PeakLevelDetector, compress, expand};
-use crate::nonlinearity::nonlinearity;
-use crate::{anti_aliasing_filter::AntiAliasingFilter, lfo, modulated_delay};
+use crate::nonlinearity::{self, nonlinearity};
+use crate::{BASE_DELAY_DEFAULT, BASE_DELAY_MAX, lfo, modulated_delay};
 use conformal_component::audio::channels_mut;
 use conformal_component::effect::{HandleParametersContext, ProcessContext};
 use conformal_component::{
-    ProcessingEnvironment, Processor,
+    ProcessingEnvironment, ProcessingMode, Processor,
     audio::{Buffer, BufferMut, ChannelLayout},
     effect::Effect as EffectT,
     pgrab, pzip,
 };
+use dsp::f32::{lerp, lerp_clamped};
 use dsp::iir::dc_blocker::DcBlocker;
 use itertools::izip;
 use num_derive::FromPrimitive;
@@ -25,6 +28,13 @@ struct DelayChannel {
     dc_blocker: DcBlocker,
     dc_blocker_high: DcBlocker,
     detector: PeakLevelDetector,
+    nonlinearity: nonlinearity::Coefficients,
+    stages: f32,
+    hiss: Hiss,
+    clock_bleed: ClockBleed,
+
+    // The most recent output, used when processing with feedback.
+    last_output: f32,
 }
 
 const DIMENSION_PAD: f32 = 0.3;
@@ -47,18 +57,99 @@ enum RoutingSetting {
     Jazz,
     Ens1,
     Ens2,
+    Flanger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+enum MixModeSetting {
+    // The wet signal is added on top of the dry signal, as in most chorus pedals.
+    Add,
+
+    // Crossfades from dry to wet, so at 100% only the wet signal is heard (i.e., vibrato).
+    Balance,
+}
+
+/// Gains for the dry and wet signals.
+#[derive(Debug, Clone, Copy)]
+struct Mix {
+    dry: f32,
+    wet: f32,
+
+    // If set, the wet taps are averaged rather than summed, so the fully wet signal
+    // is at about the level of the dry signal whatever the routing.
+    average_taps: bool,
+}
+
+impl Mix {
+    /// Mixes the dry signal `dry` with the delay taps `taps`.
+    #[allow(clippy::cast_precision_loss)]
+    fn mix<const N: usize>(self, dry: f32, taps: [f32; N]) -> f32 {
+        let wet = taps.iter().sum::<f32>();
+        let wet = if self.average_taps {
+            wet / N as f32
+        } else {
+            wet
+        };
+        dry * self.dry + wet * self.wet
+    }
+}
+
+impl MixModeSetting {
+    /// `mix` is between 0 and 1.
+    fn gains(self, mix: f32) -> Mix {
+        match self {
+            MixModeSetting::Add => Mix {
+                dry: 1.,
+                wet: mix,
+                average_taps: false,
+            },
+            MixModeSetting::Balance => Mix {
+                dry: 1. - mix,
+                wet: mix,
+                average_taps: true,
+            },
+        }
+    }
+}
+
+/// Interpolation quality settings for the modulated delay lines.
+#[derive(Debug, Clone, Copy)]
+struct DelayQuality {
+    lookaround: u8,
+    num_phases: u16,
+}
+
+const REALTIME_QUALITY: DelayQuality = DelayQuality {
+    lookaround: 8,
+    num_phases: 1025,
+};
+
+// When rendering offline we can afford a longer, finer interpolation kernel.
+const OFFLINE_QUALITY: DelayQuality = DelayQuality {
+    lookaround: 16,
+    num_phases: 4097,
+};
+
+impl DelayQuality {
+    fn for_processing_mode(processing_mode: ProcessingMode) -> Self {
+        match processing_mode {
+            ProcessingMode::Offline => OFFLINE_QUALITY,
+            ProcessingMode::Realtime | ProcessingMode::Prefetch => REALTIME_QUALITY,
+        }
+    }
 }
 
 impl DelayChannel {
     fn new(
-        lookaround: u16,
+        quality: DelayQuality,
         max_delay: usize,
         sampling_rate: f32,
         max_samples_per_process_call: usize,
     ) -> Self {
         Self {
             delay: modulated_delay::ModulatedDelay::new(modulated_delay::Options {
-                lookaround,
+                lookaround: u16::from(quality.lookaround),
+                num_phases: quality.num_phases,
                 max_delay,
                 max_samples_per_process_call,
             }),
@@ -67,6 +158,11 @@ impl DelayChannel {
             dc_blocker: DcBlocker::new(sampling_rate),
             dc_blocker_high: DcBlocker::new_with_custom_cutoff(sampling_rate, DIMENSION_CUTOFF),
             detector: PeakLevelDetector::new(sampling_rate),
+            nonlinearity: nonlinearity::Coefficients::default(),
+            stages: DEFAULT_STAGES,
+            hiss: Hiss::default(),
+            clock_bleed: ClockBleed::default(),
+            last_output: 0.0,
         }
     }
 
@@ -77,11 +173,31 @@ impl DelayChannel {
         self.dc_blocker.reset();
         self.dc_blocker_high.reset();
         self.detector.reset();
+        self.hiss.reset();
+        self.clock_bleed.reset();
+        self.last_output = 0.0;
+    }
+
+    /// Models a device with `stages` stages, clocked to give a delay of about `delay` seconds.
+    ///
+    /// `vintage` sets the level of the modeled noise, between 0 and 1.
+    pub fn set_bbd(&mut self, stages: f32, delay: f32, vintage: f32) {
+        self.nonlinearity = nonlinearity::Coefficients::for_stages(stages);
+        self.stages = stages;
+        self.hiss.set_level(vintage, stages);
+        self.clock_bleed.set_level(vintage);
+        let cutoff = anti_aliasing_filter::DEFAULT_CUTOFF * clock_rate(stages, delay)
+            / clock_rate(DEFAULT_STAGES, BASE_DELAY_DEFAULT * MS_SCALE);
+        self.pre_filter.set_cutoff(cutoff);
+        self.post_filter.set_cutoff(cutoff);
     }
 
+    /// `clock_delay` is the delay in samples of the LFO that clocks this BBD, which sets
+    /// the pitch of the modeled clock bleed.
     pub fn process<'a>(
         &'a mut self,
         input: impl Iterator<Item = f32> + 'a,
+        clock_delay: impl Iterator<Item = f32> + 'a,
         highpass_cutoff: HighpassCutoffSetting,
     ) -> modulated_delay::Buffer<'a, impl dsp::look_behind::SliceLike> {
         match highpass_cutoff {
@@ -92,31 +208,111 @@ impl DelayChannel {
             HighpassCutoffSetting::Low => &mut self.dc_blocker,
             HighpassCutoffSetting::High => &mut self.dc_blocker_high,
         };
+        let stages = self.stages;
+        let clock_bleed = &mut self.clock_bleed;
+
+        // Hiss is added in the BBD, so is shaped by the reconstruction filter, while the
+        // clock bleed is what's left after that filter.
         self.delay.process(
             self.post_filter
                 .process(self.pre_filter.process(input).map(|x| {
                     let detected_level = self.detector.detect_level(x);
                     dc_blocker.process(expand(
-                        nonlinearity(compress(x, detected_level)),
+                        nonlinearity(compress(x, detected_level), self.nonlinearity),
                         detected_level,
-                    ))
-                })),
+                    )) + self.hiss.process()
+                }))
+                .zip(clock_delay)
+                .map(move |(x, d)| x + clock_bleed.process(clock_rate(stages, d))),
         )
     }
+
+    /// Like `process`, but reads the delay line as it goes, so that the output can be fed
+    /// back through the whole BBD model, scaled by `feedback`.
+    ///
+    /// Since each output depends on the previous one, this has to run a sample at a time.
+    pub fn process_with_feedback<'a>(
+        &'a mut self,
+        input: impl Iterator<Item = f32> + 'a,
+        delay: impl Iterator<Item = f32> + 'a,
+        feedback: impl Iterator<Item = f32> + 'a,
+        highpass_cutoff: HighpassCutoffSetting,
+    ) -> impl Iterator<Item = f32> + 'a {
+        izip!(input, delay, feedback).map(move |(x, d, feedback)| {
+            let output = self
+                .process(
+                    std::iter::once(x + feedback * self.last_output),
+                    std::iter::once(d),
+                    highpass_cutoff,
+                )
+                .process(std::iter::once(d))
+                .next()
+                .unwrap();
+            self.last_output = output;
+            output
+        })
+    }
+}
+
+/// The clock rate of a BBD with `stages` stages delaying by `delay` seconds.
+///
+/// Each clock cycle moves the signal through two stages.
+fn clock_rate(stages: f32, delay: f32) -> f32 {
+    stages / (2. * delay)
+}
+
+const DEFAULT_STAGES: f32 = 256.;
+
+/// Converts the `bbd_stages` parameter into a number of stages.
+fn stages_for_setting(setting: u32) -> f32 {
+    DEFAULT_STAGES * cast::<u32, f32>(1 << setting).unwrap()
 }
 
 const NUM_LFOS: usize = 4;
 const NUM_DELAY_CHANNELS: usize = 4;
 
+// The chorus delay range at the default base delay. Changing the base delay scales
+// the whole range.
+const CHORUS_MIN_DELAY: f32 = 0.00166;
+const CHORUS_MAX_DELAY: f32 = 0.00535;
+
+const MS_SCALE: f32 = 1. / 1000.;
+
+// Flangers use much shorter delays than choruses, so they get their own LFO.
+const FLANGER_MIN_DELAY: f32 = 0.0003;
+const FLANGER_MAX_DELAY: f32 = 0.004;
+
 pub struct Effect {
     lfo: [lfo::Lfo; NUM_LFOS],
+    flanger_lfo: lfo::Lfo,
     rate_to_incr_scale: f32,
+    sampling_rate: f32,
     delay_floor: f32,
     delay_ceiling: f32,
     channels: [DelayChannel; NUM_DELAY_CHANNELS],
     lfo_forward: [Vec<f32>; NUM_LFOS],
     lfo_reverse: [Vec<f32>; NUM_LFOS],
-    mixed: Vec<f32>,
+    flanger_forward: Vec<f32>,
+    flanger_reverse: Vec<f32>,
+
+    /// The `bbd_stages` setting of `channels`, picked up on the first process call after
+    /// a reset.
+    stages: Option<u32>,
+
+    /// Changing the stage count swaps out the whole device, which would click, so we
+    /// keep the previous device running here and crossfade to the new one.
+    previous_channels: [DelayChannel; NUM_DELAY_CHANNELS],
+    stage_crossfade: Option<StageCrossfade>,
+    stage_crossfade_step: f32,
+
+    /// The output of the previous device during a stage crossfade, for each channel.
+    previous_output: [Vec<f32>; 2],
+
+    /// How far we are towards widening a dual-mono input, from 0 to 1, for each frame of
+    /// the current block.
+    dual_mono: Vec<f32>,
+    dual_mono_amount: f32,
+    dual_mono_step: f32,
 }
 
 impl Processor for Effect {
@@ -126,69 +322,252 @@ impl Processor for Effect {
             for lfo in &mut self.lfo {
                 lfo.reset();
             }
+            self.flanger_lfo.reset();
             for channel in &mut self.channels {
                 channel.reset();
             }
+            for channel in &mut self.previous_channels {
+                channel.reset();
+            }
+            self.stages = None;
+            self.stage_crossfade = None;
+            self.dual_mono_amount = 0.;
         }
     }
 }
 
 const PERCENT_SCALE: f32 = 1. / 100.;
 
+/// How long we take to move between the true-stereo and dual-mono mixes.
+const DUAL_MONO_CROSSFADE_SECONDS: f32 = 0.05;
+
+/// How long we take to crossfade between devices when the stage count changes.
+const STAGE_CROSSFADE_SECONDS: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy)]
+struct StageCrossfade {
+    previous_stages: u32,
+
+    /// How far we are through the crossfade, from 0 to 1.
+    ///
+    /// This starts out negative, so that the new device has time to fill its delay line
+    /// before we start fading it in.
+    position: f32,
+}
+
+/// The routing settings for one process call.
+#[derive(Debug, Clone, Copy)]
+struct RoutingParams {
+    routing: RoutingSetting,
+    highpass_cutoff: HighpassCutoffSetting,
+    extra_depth_scale: f32,
+}
+
+/// The range of the chorus LFOs in samples, for a base delay in seconds.
+fn chorus_lfo_options(base_delay: f32, sampling_rate: f32, delay_floor: f32) -> lfo::Options {
+    let scale = base_delay / (BASE_DELAY_DEFAULT * MS_SCALE);
+    let mut min_delay = CHORUS_MIN_DELAY * scale * sampling_rate;
+    if min_delay < delay_floor {
+        min_delay = delay_floor;
+    }
+    let mut max_delay = CHORUS_MAX_DELAY * scale * sampling_rate;
+    if max_delay < min_delay {
+        max_delay = min_delay + 1.0;
+    }
+    lfo::Options {
+        min: min_delay,
+        max: max_delay,
+    }
+}
+
 impl Effect {
     pub fn new(env: &ProcessingEnvironment) -> Self {
-        const LOOKAROUND: u8 = 8;
+        let quality = DelayQuality::for_processing_mode(env.processing_mode);
+        let delay_floor = f32::from(quality.lookaround);
 
-        let mut min_delay = 0.00166 * env.sampling_rate;
-        if min_delay < f32::from(LOOKAROUND) {
-            min_delay = f32::from(LOOKAROUND);
-        }
-        let mut max_delay = 0.00535 * env.sampling_rate;
-        if max_delay < min_delay {
-            max_delay = min_delay + 1.0;
-        }
+        let options = chorus_lfo_options(
+            BASE_DELAY_DEFAULT * MS_SCALE,
+            env.sampling_rate,
+            delay_floor,
+        );
+
+        // Leave room for the longest base delay.
+        let lfo::Options {
+            min: min_delay,
+            max: max_delay,
+        } = chorus_lfo_options(BASE_DELAY_MAX * MS_SCALE, env.sampling_rate, delay_floor);
+        let flanger_min_delay =
+            (FLANGER_MIN_DELAY * env.sampling_rate).max(f32::from(quality.lookaround));
+        let flanger_max_delay =
+            (FLANGER_MAX_DELAY * env.sampling_rate).max(flanger_min_delay + 1.0);
         let max_delay_for_buffer = max_delay + (max_delay - min_delay) * 0.5;
         let max_delay_for_buffer_samples = cast::<f32, usize>(max_delay_for_buffer.ceil()).unwrap();
-        Effect {
-            lfo: array::from_fn(|_| {
-                lfo::Lfo::new(lfo::Options {
-                    min: min_delay,
-                    max: max_delay,
-                })
-            }),
-            rate_to_incr_scale: 1. / env.sampling_rate,
-            delay_floor: f32::from(LOOKAROUND),
-            delay_ceiling: cast::<usize, f32>(max_delay_for_buffer_samples).unwrap(),
-            channels: array::from_fn(|_| {
+        let new_channels = || {
+            array::from_fn(|_| {
                 DelayChannel::new(
-                    u16::from(LOOKAROUND),
+                    quality,
                     max_delay_for_buffer_samples,
                     env.sampling_rate,
                     env.max_samples_per_process_call,
                 )
-            }),
+            })
+        };
+        Effect {
+            lfo: array::from_fn(|index| lfo::Lfo::new(options, cast(index).unwrap())),
+            flanger_lfo: lfo::Lfo::new(
+                lfo::Options {
+                    min: flanger_min_delay,
+                    max: flanger_max_delay,
+                },
+                cast(NUM_LFOS).unwrap(),
+            ),
+            rate_to_incr_scale: 1. / env.sampling_rate,
+            sampling_rate: env.sampling_rate,
+            delay_floor,
+            delay_ceiling: cast::<usize, f32>(max_delay_for_buffer_samples).unwrap(),
+            channels: new_channels(),
             lfo_forward: array::from_fn(|_| vec![0.; env.max_samples_per_process_call]),
             lfo_reverse: array::from_fn(|_| vec![0.; env.max_samples_per_process_call]),
-            mixed: vec![0.; env.max_samples_per_process_call],
+            flanger_forward: vec![0.; env.max_samples_per_process_call],
+            flanger_reverse: vec![0.; env.max_samples_per_process_call],
+            stages: None,
+            previous_channels: new_channels(),
+            stage_crossfade: None,
+            stage_crossfade_step: 1. / (STAGE_CROSSFADE_SECONDS * env.sampling_rate),
+            previous_output: array::from_fn(|_| vec![0.; env.max_samples_per_process_call]),
+            dual_mono: vec![0.; env.max_samples_per_process_call],
+            dual_mono_amount: 0.,
+            dual_mono_step: 1. / (DUAL_MONO_CROSSFADE_SECONDS * env.sampling_rate),
+        }
+    }
+
+    /// Starts a crossfade to a device for the `bbd_stages` setting `stages`, if that
+    /// differs from the current device.
+    ///
+    /// If the stage count changes again during a crossfade, we pick up the change once
+    /// the crossfade finishes.
+    fn update_stages(&mut self, stages: u32) {
+        match self.stages {
+            None => self.stages = Some(stages),
+            Some(previous_stages)
+                if previous_stages != stages && self.stage_crossfade.is_none() =>
+            {
+                std::mem::swap(&mut self.channels, &mut self.previous_channels);
+                for channel in &mut self.channels {
+                    channel.reset();
+                }
+                self.stages = Some(stages);
+                self.stage_crossfade = Some(StageCrossfade {
+                    previous_stages,
+                    position: -self.delay_ceiling * self.stage_crossfade_step,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// `base_delay` is in seconds.
+    fn set_bbd(&mut self, base_delay: f32, vintage: f32, routing: RoutingSetting) {
+        let options = chorus_lfo_options(base_delay, self.sampling_rate, self.delay_floor);
+        for lfo in &mut self.lfo {
+            lfo.set_options(options);
+        }
+        let bbd_delay = if routing == RoutingSetting::Flanger {
+            (FLANGER_MIN_DELAY + FLANGER_MAX_DELAY) * 0.5
+        } else {
+            base_delay
+        };
+        let stages = stages_for_setting(self.stages.unwrap_or_default());
+        for channel in &mut self.channels {
+            channel.set_bbd(stages, bbd_delay, vintage);
+        }
+        if let Some(StageCrossfade {
+            previous_stages, ..
+        }) = self.stage_crossfade
+        {
+            for channel in &mut self.previous_channels {
+                channel.set_bbd(stages_for_setting(previous_stages), bbd_delay, vintage);
+            }
         }
     }
 
+    fn run_lfos(
+        &mut self,
+        num_frames: usize,
+        rates: [f32; NUM_LFOS],
+        depth: f32,
+        shape: lfo::Shape,
+    ) {
+        for (lfo, forward, reverse, rate) in izip!(
+            &mut self.lfo,
+            &mut self.lfo_forward,
+            &mut self.lfo_reverse,
+            rates
+        ) {
+            lfo.run(
+                lfo::Parameters {
+                    incr: rate * self.rate_to_incr_scale,
+                    depth,
+                    shape,
+                },
+                &mut forward[..num_frames],
+                &mut reverse[..num_frames],
+            );
+        }
+        self.flanger_lfo.run(
+            lfo::Parameters {
+                incr: rates[0] * self.rate_to_incr_scale,
+                depth,
+                shape,
+            },
+            &mut self.flanger_forward[..num_frames],
+            &mut self.flanger_reverse[..num_frames],
+        );
+    }
+
     fn reset_unused_channels(&mut self, used_channels: usize) {
         for channel in &mut self.channels[used_channels..] {
             channel.reset();
         }
     }
 
+    /// A mono source on a stereo track reaches us with the same signal on both sides,
+    /// which some stereo routings would leave mono. To widen it, those routings
+    /// crossfade to a mix that spreads the delay taps across the sides.
+    ///
+    /// Silence tells us nothing about the source, so it leaves the mix as it was.
+    fn update_dual_mono(&mut self, input: &impl Buffer) {
+        let target = if input.channel(0) != input.channel(1) {
+            Some(0.)
+        } else if input.channel(0).iter().any(|x| *x != 0.) {
+            Some(1.)
+        } else {
+            None
+        };
+        let target = target.unwrap_or(self.dual_mono_amount);
+        for amount in &mut self.dual_mono[..input.num_frames()] {
+            self.dual_mono_amount = if self.dual_mono_amount < target {
+                (self.dual_mono_amount + self.dual_mono_step).min(target)
+            } else {
+                (self.dual_mono_amount - self.dual_mono_step).max(target)
+            };
+            *amount = self.dual_mono_amount;
+        }
+    }
+
     fn process_mono_dual(
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         self.reset_unused_channels(1);
-        let delay_buffer =
-            self.channels[0].process(input.channel(0).iter().copied(), highpass_cutoff);
+        let delay_buffer = self.channels[0].process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
         dsp::iter::move_into(
             izip!(
                 input.channel(0),
@@ -196,7 +575,7 @@ impl Effect {
                 delay_buffer.process(self.lfo_reverse[0].iter().copied()),
                 mix
             )
-            .map(|(i, l, r, m)| i + (l + r) * m * PERCENT_SCALE),
+            .map(|(i, l, r, m)| m.mix(*i, [l, r])),
             output.channel_mut(0),
         );
     }
@@ -205,19 +584,22 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         self.reset_unused_channels(1);
-        let delay_buffer =
-            self.channels[0].process(input.channel(0).iter().copied(), highpass_cutoff);
+        let delay_buffer = self.channels[0].process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
         dsp::iter::move_into(
             izip!(
                 input.channel(0),
                 delay_buffer.process(self.lfo_forward[0].iter().copied()),
                 mix
             )
-            .map(|(i, delayed, m)| i + delayed * m * PERCENT_SCALE),
+            .map(|(i, delayed, m)| m.mix(*i, [delayed])),
             output.channel_mut(0),
         );
     }
@@ -226,28 +608,39 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         self.reset_unused_channels(2);
         let [cl, cr, ..] = &mut self.channels;
-        let processed_l = cl.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_r = cr.process(input.channel(1).iter().copied(), highpass_cutoff);
+        let processed_l = cl.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_r = cr.process(
+            input.channel(1).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
         let mut outputs = channels_mut(output);
         let output_l = outputs.next().unwrap();
         let output_r = outputs.next().unwrap();
 
-        for (il, ir, dl, dr, ol, or, m) in izip!(
+        // For dual-mono input, both sides would be identical, so we invert the right wet
+        // signal as in stereo pedals.
+        for (il, ir, dl, dr, ol, or, m, w) in izip!(
             input.channel(0),
             input.channel(1),
             processed_l.process(self.lfo_forward[0].iter().copied()),
             processed_r.process(self.lfo_forward[0].iter().copied()),
             output_l,
             output_r,
-            mix
+            mix,
+            &self.dual_mono
         ) {
-            *ol = il + dl * m * PERCENT_SCALE;
-            *or = ir + dr * m * PERCENT_SCALE;
+            *ol = m.mix(*il, [dl]);
+            *or = m.mix(*ir, [lerp(dr, -dl, *w)]);
         }
     }
 
@@ -255,13 +648,14 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         let mixed = izip!(input.channel(0), input.channel(1)).map(|(l, r)| (l + r) * 0.5);
         self.reset_unused_channels(1);
 
-        let delay_buffer = self.channels[0].process(mixed, highpass_cutoff);
+        let delay_buffer =
+            self.channels[0].process(mixed, self.lfo_forward[0].iter().copied(), highpass_cutoff);
         let mut outputs = channels_mut(output);
         let output_l = outputs.next().unwrap();
         let output_r = outputs.next().unwrap();
@@ -274,9 +668,9 @@ impl Effect {
             output_r,
             mix
         ) {
-            let wet = delayed * m * PERCENT_SCALE;
-            *ol = il + wet;
-            *or = ir - wet;
+            let wet = delayed * m.wet;
+            *ol = il * m.dry + wet;
+            *or = ir * m.dry - wet;
         }
     }
 
@@ -284,13 +678,14 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         let mixed = izip!(input.channel(0), input.channel(1)).map(|(l, r)| (l + r) * 0.5);
         self.reset_unused_channels(1);
 
-        let delay_buffer = self.channels[0].process(mixed, highpass_cutoff);
+        let delay_buffer =
+            self.channels[0].process(mixed, self.lfo_forward[0].iter().copied(), highpass_cutoff);
 
         dsp::iter::move_into(
             izip!(
@@ -298,7 +693,7 @@ impl Effect {
                 delay_buffer.process(self.lfo_forward[0].iter().copied()),
                 mix.clone()
             )
-            .map(|(i, l, m)| i + l * m * PERCENT_SCALE),
+            .map(|(i, l, m)| m.mix(*i, [l])),
             output.channel_mut(0),
         );
         dsp::iter::move_into(
@@ -307,7 +702,7 @@ impl Effect {
                 delay_buffer.process(self.lfo_reverse[0].iter().copied()),
                 mix
             )
-            .map(|(i, r, m)| i + r * m * PERCENT_SCALE),
+            .map(|(i, r, m)| m.mix(*i, [r])),
             output.channel_mut(1),
         );
     }
@@ -317,13 +712,21 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         self.reset_unused_channels(2);
         let [cl, cr, ..] = &mut self.channels;
-        let processed_l = cl.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_r = cr.process(input.channel(1).iter().copied(), highpass_cutoff);
+        let processed_l = cl.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_r = cr.process(
+            input.channel(1).iter().copied(),
+            self.lfo_reverse[0].iter().copied(),
+            highpass_cutoff,
+        );
         let mut outputs = channels_mut(output);
         let output_l = outputs.next().unwrap();
         let output_r = outputs.next().unwrap();
@@ -337,8 +740,70 @@ impl Effect {
             output_r,
             mix
         ) {
-            *ol = (dl * DIMENSION_PAD + dr * (1.0 - DIMENSION_PAD)) * m * PERCENT_SCALE + il;
-            *or = (dr * DIMENSION_PAD + dl * (1.0 - DIMENSION_PAD)) * m * PERCENT_SCALE + ir;
+            *ol = m.mix(*il, [dl * DIMENSION_PAD + dr * (1.0 - DIMENSION_PAD)]);
+            *or = m.mix(*ir, [dr * DIMENSION_PAD + dl * (1.0 - DIMENSION_PAD)]);
+        }
+    }
+
+    fn process_mono_flanger(
+        &mut self,
+        input: &impl Buffer,
+        output: &mut impl BufferMut,
+        mix: impl Iterator<Item = Mix> + Clone,
+        feedback: impl Iterator<Item = f32>,
+        highpass_cutoff: HighpassCutoffSetting,
+    ) {
+        self.reset_unused_channels(1);
+        let delayed = self.channels[0].process_with_feedback(
+            input.channel(0).iter().copied(),
+            self.flanger_forward.iter().copied(),
+            feedback,
+            highpass_cutoff,
+        );
+        dsp::iter::move_into(
+            izip!(input.channel(0), delayed, mix).map(|(i, d, m)| m.mix(*i, [d])),
+            output.channel_mut(0),
+        );
+    }
+
+    // Each side is flanged independently, with opposite sweeps for a wider image.
+    fn process_flanger(
+        &mut self,
+        input: &impl Buffer,
+        output: &mut impl BufferMut,
+        mix: impl Iterator<Item = Mix> + Clone,
+        feedback: impl Iterator<Item = f32> + Clone,
+        highpass_cutoff: HighpassCutoffSetting,
+    ) {
+        self.reset_unused_channels(2);
+        let [cl, cr, ..] = &mut self.channels;
+        let processed_l = cl.process_with_feedback(
+            input.channel(0).iter().copied(),
+            self.flanger_forward.iter().copied(),
+            feedback.clone(),
+            highpass_cutoff,
+        );
+        let processed_r = cr.process_with_feedback(
+            input.channel(1).iter().copied(),
+            self.flanger_reverse.iter().copied(),
+            feedback,
+            highpass_cutoff,
+        );
+        let mut outputs = channels_mut(output);
+        let output_l = outputs.next().unwrap();
+        let output_r = outputs.next().unwrap();
+
+        for (il, ir, dl, dr, ol, or, m) in izip!(
+            input.channel(0),
+            input.channel(1),
+            processed_l,
+            processed_r,
+            output_l,
+            output_r,
+            mix
+        ) {
+            *ol = m.mix(*il, [dl]);
+            *or = m.mix(*ir, [dr]);
         }
     }
 
@@ -346,14 +811,30 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
         let [c0, c1, c2, c3] = &mut self.channels;
-        let processed_0 = c0.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_1 = c1.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_2 = c2.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_3 = c3.process(input.channel(0).iter().copied(), highpass_cutoff);
+        let processed_0 = c0.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_1 = c1.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_2 = c2.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[1].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_3 = c3.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[1].iter().copied(),
+            highpass_cutoff,
+        );
 
         dsp::iter::move_into(
             izip!(
@@ -364,7 +845,7 @@ impl Effect {
                 processed_3.process(self.lfo_reverse[1].iter().copied()),
                 mix
             )
-            .map(|(i, d0, d1, d2, d3, m)| i + (d0 + d1 + d2 + d3) * m * PERCENT_SCALE),
+            .map(|(i, d0, d1, d2, d3, m)| m.mix(*i, [d0, d1, d2, d3])),
             output.channel_mut(0),
         );
     }
@@ -373,15 +854,31 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
         extra_depth_scale: f32,
     ) {
         let [c0, c1, c2, c3] = &mut self.channels;
-        let processed_0 = c0.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_1 = c1.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_2 = c2.process(input.channel(0).iter().copied(), highpass_cutoff);
-        let processed_3 = c3.process(input.channel(0).iter().copied(), highpass_cutoff);
+        let processed_0 = c0.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_1 = c1.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_2 = c2.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[1].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_3 = c3.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[1].iter().copied(),
+            highpass_cutoff,
+        );
         let delay_floor = self.delay_floor;
         let delay_ceiling = self.delay_ceiling;
 
@@ -414,37 +911,46 @@ impl Effect {
                 ),
                 mix
             )
-            .map(|(i, d0, d1, d2, d3, m)| i + (d0 + d1 + d2 + d3) * m * PERCENT_SCALE),
+            .map(|(i, d0, d1, d2, d3, m)| m.mix(*i, [d0, d1, d2, d3])),
             output.channel_mut(0),
         );
     }
 
+    // True-stereo ensemble, where each side is chorused independently
     fn process_ens1(
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
     ) {
-        for (mixed, l, r) in izip!(
-            &mut self.mixed[..input.num_frames()],
-            input.channel(0),
-            input.channel(1)
-        ) {
-            *mixed = (l + r) * 0.5;
-        }
-        let mixed = &self.mixed[..input.num_frames()];
-
+        // Each side gets its own pair of delay channels, driven by the two phases of its own LFO.
         let [c0, c1, c2, c3] = &mut self.channels;
-        let processed_0 = c0.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_1 = c1.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_2 = c2.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_3 = c3.process(mixed.iter().copied(), highpass_cutoff);
+        let processed_0 = c0.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_1 = c1.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_2 = c2.process(
+            input.channel(1).iter().copied(),
+            self.lfo_forward[1].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_3 = c3.process(
+            input.channel(1).iter().copied(),
+            self.lfo_reverse[1].iter().copied(),
+            highpass_cutoff,
+        );
         let mut outputs = channels_mut(output);
         let output_l = outputs.next().unwrap();
         let output_r = outputs.next().unwrap();
 
-        for (il, ir, dl0, dl1, dr0, dr1, ol, or, m) in izip!(
+        for (il, ir, dl0, dl1, dr0, dr1, ol, or, m, w) in izip!(
             input.channel(0),
             input.channel(1),
             processed_0.process(self.lfo_forward[0].iter().copied()),
@@ -453,11 +959,13 @@ impl Effect {
             processed_3.process(self.lfo_reverse[1].iter().copied()),
             output_l,
             output_r,
-            mix
+            mix,
+            &self.dual_mono
         ) {
-            let wet_scale = m * PERCENT_SCALE;
-            *ol = il + (dl0 + dl1) * wet_scale;
-            *or = ir + (dr0 + dr1) * wet_scale;
+            // For dual-mono input, the sides would match, so we put the forward phase
+            // of each LFO on the left and the reverse phase on the right.
+            *ol = m.mix(*il, [dl0, lerp(dl1, dr0, *w)]);
+            *or = m.mix(*ir, [lerp(dr0, dl1, *w), dr1]);
         }
     }
 
@@ -465,31 +973,39 @@ impl Effect {
         &mut self,
         input: &impl Buffer,
         output: &mut impl BufferMut,
-        mix: impl Iterator<Item = f32> + Clone,
+        mix: impl Iterator<Item = Mix> + Clone,
         highpass_cutoff: HighpassCutoffSetting,
         extra_depth_scale: f32,
     ) {
-        for (mixed, l, r) in izip!(
-            &mut self.mixed[..input.num_frames()],
-            input.channel(0),
-            input.channel(1)
-        ) {
-            *mixed = (l + r) * 0.5;
-        }
-        let mixed = &self.mixed[..input.num_frames()];
-
+        // Each side gets its own pair of delay channels, driven by the two phases of its own LFO.
         let [c0, c1, c2, c3] = &mut self.channels;
-        let processed_0 = c0.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_1 = c1.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_2 = c2.process(mixed.iter().copied(), highpass_cutoff);
-        let processed_3 = c3.process(mixed.iter().copied(), highpass_cutoff);
+        let processed_0 = c0.process(
+            input.channel(0).iter().copied(),
+            self.lfo_forward[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_1 = c1.process(
+            input.channel(0).iter().copied(),
+            self.lfo_reverse[0].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_2 = c2.process(
+            input.channel(1).iter().copied(),
+            self.lfo_forward[1].iter().copied(),
+            highpass_cutoff,
+        );
+        let processed_3 = c3.process(
+            input.channel(1).iter().copied(),
+            self.lfo_reverse[1].iter().copied(),
+            highpass_cutoff,
+        );
         let delay_floor = self.delay_floor;
         let delay_ceiling = self.delay_ceiling;
         let mut outputs = channels_mut(output);
         let output_l = outputs.next().unwrap();
         let output_r = outputs.next().unwrap();
 
-        for (il, ir, dl0, dl1, dr0, dr1, ol, or, m) in izip!(
+        for (il, ir, dl0, dl1, dr0, dr1, ol, or, m, w) in izip!(
             input.channel(0),
             input.channel(1),
             processed_0.process(self.lfo_forward[0].iter().copied()),
@@ -518,69 +1034,28 @@ impl Effect {
             ),
             output_l,
             output_r,
-            mix
+            mix,
+            &self.dual_mono
         ) {
-            let wet_scale = m * PERCENT_SCALE;
-            *ol = il + (dl0 + dl1) * wet_scale;
-            *or = ir + (dr0 + dr1) * wet_scale;
+            // For dual-mono input, the sides would match, so we put the forward phase
+            // of each LFO on the left and the reverse phase on the right.
+            *ol = m.mix(*il, [dl0, lerp(dl1, dr0, *w)]);
+            *or = m.mix(*ir, [lerp(dr0, dl1, *w), dr1]);
         }
     }
-}
 
-impl EffectT for Effect {
-    #[nonblocking]
-    fn handle_parameters(&mut self, _: &impl HandleParametersContext) {}
-
-    #[nonblocking]
-    fn process(
+    fn process_routing(
         &mut self,
-        context: &impl ProcessContext,
         input: &impl Buffer,
         output: &mut impl BufferMut,
+        mix: impl Iterator<Item = Mix> + Clone,
+        feedback: impl Iterator<Item = f32> + Clone,
+        RoutingParams {
+            routing,
+            highpass_cutoff,
+            extra_depth_scale,
+        }: RoutingParams,
     ) {
-        debug_assert_eq!(input.channel_layout(), output.channel_layout());
-        debug_assert_eq!(input.num_frames(), output.num_frames());
-        let rate_to_incr_scale = self.rate_to_incr_scale;
-        let parameters = context.parameters();
-        let (rate, rate_2, rate_3, rate_4, depth, ens_2_depth, bypass, highpass_cutoff, routing) = pgrab!(parameters[numeric "rate", numeric "rate_2", numeric "rate_3", numeric "rate_4", numeric "depth", numeric "ens_2_depth", switch "bypass", enum "highpass_cutoff", enum "routing"]);
-        self.lfo[0].run(
-            lfo::Parameters {
-                incr: rate * rate_to_incr_scale,
-                depth,
-            },
-            &mut self.lfo_forward[0][..input.num_frames()],
-            &mut self.lfo_reverse[0][..input.num_frames()],
-        );
-        self.lfo[1].run(
-            lfo::Parameters {
-                incr: rate_2 * rate_to_incr_scale,
-                depth,
-            },
-            &mut self.lfo_forward[1][..input.num_frames()],
-            &mut self.lfo_reverse[1][..input.num_frames()],
-        );
-        self.lfo[2].run(
-            lfo::Parameters {
-                incr: rate_3 * rate_to_incr_scale,
-                depth,
-            },
-            &mut self.lfo_forward[2][..input.num_frames()],
-            &mut self.lfo_reverse[2][..input.num_frames()],
-        );
-        self.lfo[3].run(
-            lfo::Parameters {
-                incr: rate_4 * rate_to_incr_scale,
-                depth,
-            },
-            &mut self.lfo_forward[3][..input.num_frames()],
-            &mut self.lfo_reverse[3][..input.num_frames()],
-        );
-        let mix = pzip!(parameters[numeric "mix"]).map(move |mix| if bypass { 0.0 } else { mix });
-        let extra_depth_scale = ens_2_depth * PERCENT_SCALE;
-
-        let highpass_cutoff =
-            FromPrimitive::from_u32(highpass_cutoff).unwrap_or(HighpassCutoffSetting::Low);
-        let routing = FromPrimitive::from_u32(routing).unwrap_or(RoutingSetting::Synth);
         match input.channel_layout() {
             ChannelLayout::Mono => match routing {
                 RoutingSetting::Pedal | RoutingSetting::Jazz => {
@@ -595,6 +1070,9 @@ impl EffectT for Effect {
                 RoutingSetting::Ens2 => {
                     self.process_mono_ens2(input, output, mix, highpass_cutoff, extra_depth_scale);
                 }
+                RoutingSetting::Flanger => {
+                    self.process_mono_flanger(input, output, mix, feedback, highpass_cutoff);
+                }
             },
             ChannelLayout::Stereo => match routing {
                 RoutingSetting::Synth => {
@@ -615,22 +1093,110 @@ impl EffectT for Effect {
                 RoutingSetting::Ens2 => {
                     self.process_ens2(input, output, mix, highpass_cutoff, extra_depth_scale);
                 }
+                RoutingSetting::Flanger => {
+                    self.process_flanger(input, output, mix, feedback, highpass_cutoff);
+                }
             },
         }
     }
 }
 
+impl EffectT for Effect {
+    #[nonblocking]
+    fn handle_parameters(&mut self, _: &impl HandleParametersContext) {}
+
+    #[nonblocking]
+    fn process(
+        &mut self,
+        context: &impl ProcessContext,
+        input: &impl Buffer,
+        output: &mut impl BufferMut,
+    ) {
+        debug_assert_eq!(input.channel_layout(), output.channel_layout());
+        debug_assert_eq!(input.num_frames(), output.num_frames());
+        let parameters = context.parameters();
+        let (rate, rate_2, rate_3, rate_4, depth, ens_2_depth, bypass, highpass_cutoff, routing) = pgrab!(parameters[numeric "rate", numeric "rate_2", numeric "rate_3", numeric "rate_4", numeric "depth", numeric "ens_2_depth", switch "bypass", enum "highpass_cutoff", enum "routing"]);
+        let (base_delay, bbd_stages, vintage) =
+            pgrab!(parameters[numeric "base_delay", enum "bbd_stages", numeric "vintage"]);
+        let (mix_mode, lfo_shape) = pgrab!(parameters[enum "mix_mode", enum "lfo_shape"]);
+        let routing = FromPrimitive::from_u32(routing).unwrap_or(RoutingSetting::Synth);
+
+        self.update_stages(bbd_stages);
+        self.set_bbd(base_delay * MS_SCALE, vintage * PERCENT_SCALE, routing);
+        self.run_lfos(
+            input.num_frames(),
+            [rate, rate_2, rate_3, rate_4],
+            depth,
+            FromPrimitive::from_u32(lfo_shape).unwrap_or(lfo::Shape::Triangle),
+        );
+        let mix_mode: MixModeSetting =
+            FromPrimitive::from_u32(mix_mode).unwrap_or(MixModeSetting::Add);
+        let mix = pzip!(parameters[numeric "mix"])
+            .map(move |mix| mix_mode.gains(if bypass { 0.0 } else { mix * PERCENT_SCALE }));
+        let feedback =
+            pzip!(parameters[numeric "feedback"]).map(|feedback| feedback * PERCENT_SCALE);
+        let extra_depth_scale = ens_2_depth * PERCENT_SCALE;
+
+        let highpass_cutoff =
+            FromPrimitive::from_u32(highpass_cutoff).unwrap_or(HighpassCutoffSetting::Low);
+        if input.channel_layout() == ChannelLayout::Stereo {
+            self.update_dual_mono(input);
+        }
+        let routing = RoutingParams {
+            routing,
+            highpass_cutoff,
+            extra_depth_scale,
+        };
+        if let Some(crossfade) = self.stage_crossfade {
+            // Run the previous device, then crossfade its output into the current one's.
+            std::mem::swap(&mut self.channels, &mut self.previous_channels);
+            self.process_routing(input, output, mix.clone(), feedback.clone(), routing);
+            std::mem::swap(&mut self.channels, &mut self.previous_channels);
+            for (channel, previous) in self.previous_output[..output.num_channels()]
+                .iter_mut()
+                .enumerate()
+            {
+                previous[..output.num_frames()].copy_from_slice(output.channel(channel));
+            }
+            self.process_routing(input, output, mix, feedback, routing);
+
+            for (channel, previous) in self.previous_output[..output.num_channels()]
+                .iter()
+                .enumerate()
+            {
+                let mut position = crossfade.position;
+                for (o, p) in output.channel_mut(channel).iter_mut().zip(previous) {
+                    position += self.stage_crossfade_step;
+                    *o = lerp_clamped(*p, *o, position);
+                }
+            }
+            let position = crossfade.position
+                + self.stage_crossfade_step * cast::<usize, f32>(output.num_frames()).unwrap();
+            self.stage_crossfade = (position < 1.).then_some(StageCrossfade {
+                position,
+                ..crossfade
+            });
+        } else {
+            self.process_routing(input, output, mix, feedback, routing);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
     use super::*;
+    use component_snapshots::effect::{
+        generate_basic_stereo_snapshot, generate_snapshot_with_params,
+    };
     use conformal_component::{
-        audio::BufferData,
+        audio::{BufferData, all_approx_eq},
         parameters::{
             BufferStates, ConstantBufferStates, InternalValue, StatesMap, override_defaults,
         },
     };
+    use snapshots::assert_snapshot;
 
     struct TestProcessContext<P> {
         parameters: P,
@@ -791,4 +1357,539 @@ mod tests {
         assert!(max_depth_0_delta < 1e-6);
         assert!(max_depth_100_delta > 1e-3);
     }
+
+    fn check_ensemble_keeps_sides_separate(routing: RoutingSetting) {
+        let overrides = HashMap::from([("routing", InternalValue::Enum(routing as u32))]);
+        let right = dsp::test_utils::sine(4096, 660.0 / 48000.0);
+        let [a_left, a_right] = generate_basic_stereo_snapshot(
+            &crate::Component {},
+            [&dsp::test_utils::sine(4096, 440.0 / 48000.0), &right],
+            &overrides,
+        );
+        let [b_left, b_right] = generate_basic_stereo_snapshot(
+            &crate::Component {},
+            [&dsp::test_utils::sine(4096, 880.0 / 48000.0), &right],
+            &overrides,
+        );
+
+        let mut max_left_delta = 0.0f32;
+        let mut max_right_delta = 0.0f32;
+        for (al, bl, ar, br) in izip!(&a_left, &b_left, &a_right, &b_right) {
+            max_left_delta = max_left_delta.max((al - bl).abs());
+            max_right_delta = max_right_delta.max((ar - br).abs());
+        }
+        assert!(max_left_delta > 1e-3);
+        assert!(max_right_delta < 1e-6);
+    }
+
+    #[test]
+    fn ens1_keeps_sides_separate() {
+        check_ensemble_keeps_sides_separate(RoutingSetting::Ens1);
+    }
+
+    #[test]
+    fn ens2_keeps_sides_separate() {
+        check_ensemble_keeps_sides_separate(RoutingSetting::Ens2);
+    }
+
+    fn stereo_snapshot(routing: RoutingSetting) -> [Vec<f32>; 2] {
+        let num_frames = 48000;
+        let left: Vec<_> = dsp::test_utils::sine(num_frames, 440. / 48000.)
+            .iter()
+            .map(|x| x * 1. / 4.)
+            .collect();
+        let right: Vec<_> = dsp::test_utils::linear_sine_sweep(num_frames, 48000., 10., 20000.)
+            .iter()
+            .map(|x| x * 1. / 4.)
+            .collect();
+        generate_basic_stereo_snapshot(
+            &crate::Component {},
+            [&left, &right],
+            &HashMap::from([("routing", InternalValue::Enum(routing as u32))]),
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_stereo_ens1() {
+        let [left, right] = stereo_snapshot(RoutingSetting::Ens1);
+        assert_snapshot!("stereo_ens1_left", 48000, left);
+        assert_snapshot!("stereo_ens1_right", 48000, right);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_stereo_ens2() {
+        let [left, right] = stereo_snapshot(RoutingSetting::Ens2);
+        assert_snapshot!("stereo_ens2_left", 48000, left);
+        assert_snapshot!("stereo_ens2_right", 48000, right);
+    }
+
+    /// Returns the error of a delay line at `quality` delaying a high sine, relative to
+    /// the sine, in dB.
+    #[allow(clippy::cast_precision_loss)]
+    fn interpolation_error_db(quality: DelayQuality) -> f32 {
+        let num_samples = 8192;
+        let incr = 15000f32 / 48000f32;
+        let min_delay = 40f32;
+        let mut variable_delay = modulated_delay::ModulatedDelay::new(modulated_delay::Options {
+            lookaround: u16::from(quality.lookaround),
+            num_phases: quality.num_phases,
+            max_delay: 42,
+            max_samples_per_process_call: num_samples,
+        });
+        let input = dsp::test_utils::sine(num_samples, incr);
+        // Slowly sweep through every fractional delay.
+        let delay: Vec<_> = (0..num_samples)
+            .map(|i| min_delay + i as f32 / num_samples as f32)
+            .collect();
+        let output: Vec<_> = variable_delay
+            .process(input.iter().copied())
+            .process(delay.iter().copied())
+            .collect();
+        let (error, signal) = output
+            .iter()
+            .zip(delay.iter())
+            .enumerate()
+            .skip(64)
+            .map(|(i, (y, d))| {
+                let expected = (std::f32::consts::TAU * incr * (i as f32 - d)).sin();
+                ((y - expected).powi(2), expected.powi(2))
+            })
+            .fold((0f32, 0f32), |(e, s), (de, ds)| (e + de, s + ds));
+        10f32 * (error / signal).log10()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn offline_quality_reduces_interpolation_error() {
+        // The rest of the BBD model masks the delay line's error in the full effect, so
+        // we measure the delay line on its own. At realtime quality the error is about
+        // -28dB, and at offline quality about -65dB.
+        let realtime =
+            interpolation_error_db(DelayQuality::for_processing_mode(ProcessingMode::Realtime));
+        let offline =
+            interpolation_error_db(DelayQuality::for_processing_mode(ProcessingMode::Offline));
+        assert!(offline < -60.0);
+        assert!(offline < realtime - 30.0);
+    }
+
+    fn process_blocks(
+        params: &ConstantBufferStates<StatesMap>,
+        channel_layout: ChannelLayout,
+        input: &[f32],
+    ) -> Vec<Vec<f32>> {
+        let block_size = 512;
+        let mut effect = Effect::new(&ProcessingEnvironment {
+            sampling_rate: 48000.0,
+            max_samples_per_process_call: block_size,
+            channel_layout,
+            processing_mode: conformal_component::ProcessingMode::Realtime,
+        });
+        effect.set_processing(true);
+        let mut output = vec![Vec::with_capacity(input.len()); channel_layout.num_channels()];
+        for chunk in input.chunks(block_size) {
+            let mut input_buffer = BufferData::new(channel_layout, chunk.len());
+            for channel in 0..channel_layout.num_channels() {
+                dsp::iter::move_into(chunk.iter().copied(), input_buffer.channel_mut(channel));
+            }
+            let mut output_buffer = BufferData::new(channel_layout, chunk.len());
+            effect.process(
+                &TestProcessContext { parameters: params },
+                &input_buffer,
+                &mut output_buffer,
+            );
+            for (channel, output) in output.iter_mut().enumerate() {
+                output.extend_from_slice(output_buffer.channel(channel));
+            }
+        }
+        output
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn flanger_is_stable_at_max_feedback() {
+        let num_frames = 48000 * 5;
+        let silence = 48000;
+        let mut noise = dsp::test_utils::white_noise(num_frames);
+        noise.resize(num_frames + silence, 0.0);
+        let mut sine = dsp::test_utils::sine(num_frames, 440.0 / 48000.0);
+        sine.resize(num_frames + silence, 0.0);
+        for feedback in [-95.0, 95.0] {
+            let params = params_for_overrides([
+                (
+                    "routing",
+                    InternalValue::Enum(RoutingSetting::Flanger as u32),
+                ),
+                ("feedback", InternalValue::Numeric(feedback)),
+                ("rate", InternalValue::Numeric(10.1)),
+            ]);
+            for channel_layout in [ChannelLayout::Mono, ChannelLayout::Stereo] {
+                for input in [&noise, &sine] {
+                    let output = process_blocks(&params, channel_layout, input);
+
+                    // With a loop gain of at most 95%, resonances can build up to at most
+                    // 20 times the input level.
+                    for channel in &output {
+                        assert!(channel.iter().all(|x| x.is_finite() && x.abs() < 20.0));
+                    }
+
+                    // Once the input stops, the feedback should die away.
+                    for channel in &output {
+                        assert!(
+                            channel[num_frames + silence / 2..]
+                                .iter()
+                                .all(|x| x.abs() < 1e-3)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn flanger_feedback_rings_after_input_stops() {
+        let mut input = dsp::test_utils::white_noise(4096);
+        input.resize(4096 + 2048, 0.0);
+        let tail_energy = |feedback: f32| {
+            let params = params_for_overrides([
+                (
+                    "routing",
+                    InternalValue::Enum(RoutingSetting::Flanger as u32),
+                ),
+                ("feedback", InternalValue::Numeric(feedback)),
+            ]);
+            process_blocks(&params, ChannelLayout::Mono, &input)[0][4096 + 512..]
+                .iter()
+                .map(|x| x * x)
+                .sum::<f32>()
+        };
+        let no_feedback = tail_energy(0.0);
+        assert!(tail_energy(90.0) > no_feedback * 10.0);
+        assert!(tail_energy(-90.0) > no_feedback * 10.0);
+    }
+
+    fn wet_signal(overrides: &[(&'static str, InternalValue)], input: &[f32]) -> Vec<f32> {
+        let mut all_overrides = [
+            ("routing", InternalValue::Enum(RoutingSetting::Pedal as u32)),
+            ("depth", InternalValue::Numeric(0.0)),
+            (
+                "base_delay",
+                InternalValue::Numeric(crate::BASE_DELAY_DEFAULT),
+            ),
+            ("bbd_stages", InternalValue::Enum(0)),
+        ];
+        for (id, value) in overrides {
+            all_overrides.iter_mut().find(|(i, _)| i == id).unwrap().1 = *value;
+        }
+        let params = params_for_overrides(all_overrides);
+        izip!(
+            input,
+            &process_blocks(&params, ChannelLayout::Mono, input)[0]
+        )
+        .map(|(i, o)| o - i)
+        .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn base_delay_sets_delay_time() {
+        let mut impulse = vec![0.0; 4096];
+        impulse[0] = 1.0;
+        for base_delay in [2.0, 5.0, 20.0] {
+            let wet = wet_signal(
+                &[("base_delay", InternalValue::Numeric(base_delay))],
+                &impulse,
+            );
+            let peak = wet
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                .unwrap()
+                .0;
+            let expected = base_delay * 48.0;
+            #[allow(clippy::cast_precision_loss)]
+            let error = (peak as f32 - expected).abs();
+
+            // Allow for the group delay of the anti-aliasing filters, which grows as
+            // the BBD clock slows down.
+            assert!(error < expected * 0.05 + 8.0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn more_stages_raise_bandwidth() {
+        let input: Vec<_> = dsp::test_utils::sine(8192, 15000.0 / 48000.0)
+            .iter()
+            .map(|x| x * 0.25)
+            .collect();
+        let wet_energy = |stages: u32| {
+            wet_signal(&[("bbd_stages", InternalValue::Enum(stages))], &input)[4096..]
+                .iter()
+                .map(|x| x * x)
+                .sum::<f32>()
+        };
+        assert!(wet_energy(2) > wet_energy(0) * 100.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn stage_changes_crossfade() {
+        const SWITCH: usize = 4096;
+        let input: Vec<_> = dsp::test_utils::sine(SWITCH * 4, 15000.0 / 48000.0)
+            .iter()
+            .map(|x| x * 0.25)
+            .collect();
+        let params_for_stages = |stages: u32| {
+            params_for_overrides([
+                ("routing", InternalValue::Enum(RoutingSetting::Pedal as u32)),
+                ("bbd_stages", InternalValue::Enum(stages)),
+            ])
+        };
+        let new_effect = || {
+            let mut effect = Effect::new(&ProcessingEnvironment {
+                sampling_rate: 48000.0,
+                max_samples_per_process_call: 512,
+                channel_layout: ChannelLayout::Mono,
+                processing_mode: conformal_component::ProcessingMode::Realtime,
+            });
+            effect.set_processing(true);
+            effect
+        };
+        let process = |stages: u32| {
+            generate_snapshot_with_params(
+                &mut new_effect(),
+                &input,
+                512,
+                &params_for_stages(stages),
+            )
+        };
+
+        let mut effect = new_effect();
+        let warmup = cast::<f32, usize>(effect.delay_ceiling).unwrap();
+        let mut switched = generate_snapshot_with_params(
+            &mut effect,
+            &input[..SWITCH],
+            512,
+            &params_for_stages(0),
+        );
+        switched.extend(generate_snapshot_with_params(
+            &mut effect,
+            &input[SWITCH..],
+            512,
+            &params_for_stages(2),
+        ));
+        let before = process(0);
+        let after = process(2);
+
+        // The previous device plays alone while the new one fills its delay line, then
+        // we crossfade between the two.
+        let fade_start = SWITCH + warmup;
+        let fade_length = 2400;
+        let fade_end = fade_start + fade_length;
+        assert!(all_approx_eq(
+            switched[..fade_start].iter().copied(),
+            before[..fade_start].iter().copied(),
+            1e-6
+        ));
+        #[allow(clippy::cast_precision_loss)]
+        let crossfade = (fade_start..fade_end).map(|frame| {
+            lerp(
+                before[frame],
+                after[frame],
+                (frame - fade_start) as f32 / fade_length as f32,
+            )
+        });
+        assert!(all_approx_eq(
+            switched[fade_start..fade_end].iter().copied(),
+            crossfade,
+            1e-3
+        ));
+        assert!(all_approx_eq(
+            switched[fade_end..].iter().copied(),
+            after[fade_end..].iter().copied(),
+            1e-3
+        ));
+
+        effect.set_processing(false);
+        effect.set_processing(true);
+        let after_reset =
+            generate_snapshot_with_params(&mut effect, &input, 512, &params_for_stages(2));
+        assert!(all_approx_eq(after_reset, after, 1e-6));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn vintage_adds_noise() {
+        let silence = vec![0.0; 48000];
+        let tail_energy = |vintage: f32| {
+            let params = params_for_overrides([("vintage", InternalValue::Numeric(vintage))]);
+            process_blocks(&params, ChannelLayout::Stereo, &silence)
+                .iter()
+                .map(|channel| channel[24000..].iter().map(|x| x * x).sum::<f32>())
+                .sum::<f32>()
+        };
+        assert!(tail_energy(100.0) > tail_energy(0.0) * 100.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn vintage_noise_is_deterministic() {
+        let params = params_for_overrides([("vintage", InternalValue::Numeric(100.0))]);
+        let input = dsp::test_utils::sine(4096, 440.0 / 48000.0);
+        let a = process_blocks(&params, ChannelLayout::Stereo, &input);
+        let b = process_blocks(&params, ChannelLayout::Stereo, &input);
+        for (a, b) in a.iter().flatten().zip(b.iter().flatten()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn balance_mode_removes_dry_signal_at_full_mix() {
+        let mut impulse = vec![0.0; 1024];
+        impulse[0] = 1.0;
+        let output = |mix_mode: MixModeSetting| {
+            let params = params_for_overrides([
+                ("routing", InternalValue::Enum(RoutingSetting::Pedal as u32)),
+                ("mix", InternalValue::Numeric(100.0)),
+                ("mix_mode", InternalValue::Enum(mix_mode as u32)),
+            ]);
+            process_blocks(&params, ChannelLayout::Mono, &impulse).remove(0)
+        };
+        let add = output(MixModeSetting::Add);
+        let balance = output(MixModeSetting::Balance);
+        assert!((add[0] - 1.0).abs() < 1e-3);
+        assert!(balance[0].abs() < 1e-3);
+
+        // The wet signal is the same in both modes.
+        for (add, balance, dry) in izip!(&add, &balance, &impulse) {
+            assert!((add - dry - balance).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn balance_mode_level_does_not_depend_on_tap_count() {
+        let input = dsp::test_utils::white_noise(8192);
+        let level = |routing: RoutingSetting| {
+            let params = params_for_overrides([
+                ("routing", InternalValue::Enum(routing as u32)),
+                ("mix", InternalValue::Numeric(100.0)),
+                (
+                    "mix_mode",
+                    InternalValue::Enum(MixModeSetting::Balance as u32),
+                ),
+            ]);
+            process_blocks(&params, ChannelLayout::Mono, &input)[0][4096..]
+                .iter()
+                .map(|x| x * x)
+                .sum::<f32>()
+                .sqrt()
+        };
+
+        // Pedal has a single tap, while the others mix two or four.
+        let single_tap = level(RoutingSetting::Pedal);
+        for routing in [
+            RoutingSetting::Synth,
+            RoutingSetting::Ens1,
+            RoutingSetting::Ens2,
+        ] {
+            let ratio = level(routing) / single_tap;
+            assert!((0.5..1.5).contains(&ratio));
+        }
+    }
+
+    fn check_dual_mono_is_wide(routing: RoutingSetting) {
+        let input = dsp::test_utils::sine(8192, 440.0 / 48000.0);
+        let process = |mix: f32| {
+            generate_basic_stereo_snapshot(
+                &crate::Component {},
+                [&input, &input],
+                &HashMap::from([
+                    ("routing", InternalValue::Enum(routing as u32)),
+                    ("mix", InternalValue::Numeric(mix)),
+                ]),
+            )
+        };
+
+        // Skip the crossfade into the dual-mono mix.
+        let [left, right] = process(100.0);
+        let (mid, side) = izip!(&left, &right)
+            .skip(4096)
+            .fold((0.0, 0.0), |(mid, side), (l, r)| {
+                (mid + (l + r).powi(2), side + (l - r).powi(2))
+            });
+        assert!(side > mid * 0.01);
+
+        // With no wet signal, the input should pass through to both sides.
+        let [left, right] = process(0.0);
+        for (l, r, i) in izip!(&left, &right, &input) {
+            assert!((l - i).abs() < 1e-6);
+            assert!((r - i).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_synth_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Synth);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_dimension_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Dimension);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_pedal_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Pedal);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_jazz_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Jazz);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_ens1_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Ens1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_ens2_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Ens2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_flanger_is_wide() {
+        check_dual_mono_is_wide(RoutingSetting::Flanger);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dual_mono_widening_is_smooth() {
+        // Switch from true stereo to dual mono halfway through.
+        let left = dsp::test_utils::sine(8192, 440.0 / 48000.0);
+        let right = izip!(&left, dsp::test_utils::sine(8192, 660.0 / 48000.0))
+            .enumerate()
+            .map(|(index, (l, r))| if index < 4096 { r } else { *l })
+            .collect::<Vec<_>>();
+        let [left, _] = generate_basic_stereo_snapshot(
+            &crate::Component {},
+            [&left, &right],
+            &HashMap::from([("routing", InternalValue::Enum(RoutingSetting::Ens1 as u32))]),
+        );
+        for (a, b) in left.iter().zip(left.iter().skip(1)).skip(4000).take(200) {
+            assert!((a - b).abs() < 0.2);
+        }
+    }
 }
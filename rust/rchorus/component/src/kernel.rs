@@ -22,11 +22,11 @@ fn sinc(length: usize, increment: f32) -> Vec<f32> {
 }
 
 pub struct LpfOptions {
-    pub length: u16,
+    pub length: usize,
     pub increment: f32,
 }
 pub fn lpf(LpfOptions { length, increment }: LpfOptions) -> Vec<f32> {
-    let mut kernel = sinc(length as usize, increment);
+    let mut kernel = sinc(length, increment);
     window::blackman_harris(&mut kernel);
     kernel
 }
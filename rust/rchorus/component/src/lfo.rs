@@ -1,8 +1,16 @@
 use dsp::f32::exp_approx;
+use num_derive::FromPrimitive;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+const SEED: u64 = 369;
 
 #[derive(Clone)]
 pub struct Lfo {
     point: f32,
+
+    // When the range changes, `point` moves smoothly towards this.
+    target_point: f32,
     scale: f32,
 
     // Note that for BBDs, we average the delay over a fixed window (the LFO controls
@@ -14,6 +22,21 @@ pub struct Lfo {
 
     phase: f32,
     output: Option<f32>,
+
+    // For the random shape, we glide from `random_from` to `random_to` over each cycle.
+    seed: u64,
+    rng: Xoshiro256PlusPlus,
+    random_from: f32,
+    random_to: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+pub enum Shape {
+    Triangle,
+    Sine,
+
+    /// Smoothly glides to a new random value each cycle.
+    Random,
 }
 
 #[derive(Clone, Copy)]
@@ -28,6 +51,8 @@ pub struct Parameters {
 
     /// In percent
     pub depth: f32,
+
+    pub shape: Shape,
 }
 
 /// Time-constant in samples
@@ -36,35 +61,71 @@ fn alpha_from_time_constant(t: f32) -> f32 {
 }
 
 impl Lfo {
-    pub fn new(Options { min, max }: Options) -> Self {
+    /// `index` should differ for each LFO running together, so their random shapes
+    /// aren't in lockstep.
+    pub fn new(options: Options, index: u64) -> Self {
+        let seed = SEED + index;
+        let mut ret = Self {
+            point: 0.,
+            target_point: 0.,
+            scale: 0.,
+            alpha: 0.,
+            output: None,
+            phase: 0.,
+            seed,
+            rng: Xoshiro256PlusPlus::seed_from_u64(seed),
+            random_from: 0.,
+            random_to: 0.,
+        };
+        ret.set_options(options);
+        ret.point = ret.target_point;
+        ret.reset_random();
+        ret
+    }
+
+    /// Changes the range of the LFO without resetting it.
+    pub fn set_options(&mut self, Options { min, max }: Options) {
         assert!(min < max);
-        let point = (max + min) * 0.5;
+        self.target_point = (max + min) * 0.5;
+        self.scale = (max - min) / 100. * 2.;
 
         // Note that we use an artificially large time-constant for the smoothing here.
         // This was tuned heuristically to sound good.
-        let alpha = alpha_from_time_constant(4. * point);
+        self.alpha = alpha_from_time_constant(4. * self.target_point);
+    }
 
-        Self {
-            point,
-            scale: (max - min) / 100. * 2.,
-            alpha,
-            output: None,
-            phase: 0.,
+    /// The shape at the current phase, between -0.25 and 0.25.
+    fn shape_value(&self, shape: Shape) -> f32 {
+        match shape {
+            Shape::Triangle => {
+                (if self.phase > 0.5 {
+                    1. - self.phase
+                } else {
+                    self.phase
+                }) - 0.25
+            }
+            Shape::Sine => -0.25 * (self.phase * std::f32::consts::TAU).cos(),
+            Shape::Random => {
+                let t = 0.5 - 0.5 * (self.phase * std::f32::consts::PI).cos();
+                self.random_from + t * (self.random_to - self.random_from)
+            }
         }
     }
 
-    fn run_single(&mut self, Parameters { incr, depth }: Parameters) -> f32 {
-        let instant = depth
-            * self.scale
-            * (if self.phase > 0.5 {
-                1. - self.phase
-            } else {
-                self.phase
-            } - 0.25);
+    fn reset_random(&mut self) {
+        self.rng = Xoshiro256PlusPlus::seed_from_u64(self.seed);
+        self.random_from = 0.;
+        self.random_to = self.rng.gen_range(-0.25..=0.25);
+    }
+
+    fn run_single(&mut self, Parameters { incr, depth, shape }: Parameters) -> f32 {
+        let instant = depth * self.scale * self.shape_value(shape);
         if incr < 0.5 {
             self.phase += incr;
             if self.phase > 1. {
                 self.phase -= 1.;
+                self.random_from = self.random_to;
+                self.random_to = self.rng.gen_range(-0.25..=0.25);
             }
         }
         self.output = match self.output {
@@ -76,17 +137,19 @@ impl Lfo {
 
     pub fn run(&mut self, params: Parameters, forward: &mut [f32], reverse: &mut [f32]) {
         debug_assert_eq!(forward.len(), reverse.len());
-        let point = self.point;
         for (forward, reverse) in forward.iter_mut().zip(reverse.iter_mut()) {
             let value = self.run_single(params);
-            *forward = point + value;
-            *reverse = point - value;
+            self.point += self.alpha * (self.target_point - self.point);
+            *forward = self.point + value;
+            *reverse = self.point - value;
         }
     }
 
     pub fn reset(&mut self) {
         self.phase = 0.;
         self.output = None;
+        self.reset_random();
+        self.point = self.target_point;
     }
 }
 
@@ -96,13 +159,14 @@ mod tests {
 
     #[test]
     fn alias_surpressed() {
-        let mut lfo = Lfo::new(Options { min: 5., max: 9. });
+        let mut lfo = Lfo::new(Options { min: 5., max: 9. }, 0);
         let mut forward = [0.; 10];
         let mut reverse = [0.; 10];
         lfo.run(
             Parameters {
                 incr: 0.825,
                 depth: 100.,
+                shape: Shape::Triangle,
             },
             &mut forward,
             &mut reverse,
@@ -110,4 +174,110 @@ mod tests {
         assert_eq!(forward, [5.; 10]);
         assert_eq!(reverse, [9.; 10]);
     }
+
+    #[test]
+    fn changing_range_is_smooth() {
+        let mut lfo = Lfo::new(
+            Options {
+                min: 50.,
+                max: 150.,
+            },
+            0,
+        );
+        let mut forward = [0.; 100];
+        let mut reverse = [0.; 100];
+        let params = Parameters {
+            incr: 0.001,
+            depth: 0.,
+            shape: Shape::Triangle,
+        };
+        lfo.run(params, &mut forward, &mut reverse);
+        let before = forward[99];
+        lfo.set_options(Options {
+            min: 500.,
+            max: 1500.,
+        });
+        lfo.run(params, &mut forward, &mut reverse);
+        assert!(forward[0] - before < (1000. - before) * 0.5);
+        for (a, b) in forward.iter().zip(forward.iter().skip(1)) {
+            assert!(b > a);
+        }
+    }
+
+    fn run_shape(lfo: &mut Lfo, shape: Shape) -> Vec<f32> {
+        let mut forward = vec![0.; 4800];
+        let mut reverse = vec![0.; 4800];
+        lfo.run(
+            Parameters {
+                incr: 5. / 4800.,
+                depth: 100.,
+                shape,
+            },
+            &mut forward,
+            &mut reverse,
+        );
+        forward
+    }
+
+    #[test]
+    fn shapes_stay_in_range() {
+        for shape in [Shape::Triangle, Shape::Sine, Shape::Random] {
+            let mut lfo = Lfo::new(
+                Options {
+                    min: 50.,
+                    max: 150.,
+                },
+                0,
+            );
+            let output = run_shape(&mut lfo, shape);
+            assert!(output.iter().all(|x| (49.9..=150.1).contains(x)));
+            let (min, max) = output.iter().fold((f32::MAX, f32::MIN), |(min, max), x| {
+                (min.min(*x), max.max(*x))
+            });
+            assert!(max - min > 10.);
+        }
+    }
+
+    #[test]
+    fn random_is_smooth() {
+        let mut lfo = Lfo::new(
+            Options {
+                min: 50.,
+                max: 150.,
+            },
+            0,
+        );
+        let output = run_shape(&mut lfo, Shape::Random);
+        for (a, b) in output.iter().zip(output.iter().skip(1)) {
+            assert!((a - b).abs() < 1.);
+        }
+    }
+
+    #[test]
+    fn random_repeats_after_reset() {
+        let mut lfo = Lfo::new(
+            Options {
+                min: 50.,
+                max: 150.,
+            },
+            0,
+        );
+        let before = run_shape(&mut lfo, Shape::Random);
+        lfo.reset();
+        let after = run_shape(&mut lfo, Shape::Random);
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn random_differs_by_index() {
+        let options = Options {
+            min: 50.,
+            max: 150.,
+        };
+        let a = run_shape(&mut Lfo::new(options, 0), Shape::Random);
+        let b = run_shape(&mut Lfo::new(options, 1), Shape::Random);
+        assert!(a.iter().zip(b.iter()).any(|(a, b)| (a - b).abs() > 1.));
+    }
 }
@@ -2,7 +2,11 @@ use conformal_component::parameters::{self, InfoRef};
 use conformal_component::parameters::{Flags, TypeSpecificInfoRef};
 use conformal_component::{Component as ComponentT, ProcessingEnvironment};
 
-const PARAMETERS: [InfoRef<'static, &'static str>; 10] = [
+// Base delay of the chorus in ms. The default matches the roland synth chorus.
+const BASE_DELAY_DEFAULT: f32 = 3.505;
+const BASE_DELAY_MAX: f32 = 30.;
+
+const PARAMETERS: [InfoRef<'static, &'static str>; 16] = [
     InfoRef {
         title: "Rate",
         short_title: "Rate",
@@ -104,12 +108,84 @@ const PARAMETERS: [InfoRef<'static, &'static str>; 10] = [
         flags: Flags { automatable: true },
         type_specific: TypeSpecificInfoRef::Enum {
             default: 0,
-            values: &["Synth", "Dimension", "Pedal", "Jazz", "Ens 1", "Ens 2"],
+            values: &[
+                "Synth",
+                "Dimension",
+                "Pedal",
+                "Jazz",
+                "Ens 1",
+                "Ens 2",
+                "Flanger",
+            ],
+        },
+    },
+    InfoRef {
+        title: "Feedback",
+        short_title: "Feedback",
+        unique_id: "feedback",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.,
+            valid_range: -95f32..=95.,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Base Delay",
+        short_title: "Delay",
+        unique_id: "base_delay",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: BASE_DELAY_DEFAULT,
+            valid_range: 1f32..=BASE_DELAY_MAX,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "BBD Stages",
+        short_title: "Stages",
+        unique_id: "bbd_stages",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["256", "512", "1024", "2048", "4096"],
+        },
+    },
+    InfoRef {
+        title: "Vintage",
+        short_title: "Vintage",
+        unique_id: "vintage",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.,
+            valid_range: 0f32..=100.,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Mix Mode",
+        short_title: "Mix Mode",
+        unique_id: "mix_mode",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["Add", "Balance"],
+        },
+    },
+    InfoRef {
+        title: "LFO Shape",
+        short_title: "Shape",
+        unique_id: "lfo_shape",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["Triangle", "Sine", "Random"],
         },
     },
 ];
 
 mod anti_aliasing_filter;
+mod bbd_noise;
 mod compander;
 mod effect;
 mod kernel;
@@ -140,9 +216,9 @@ mod tests {
     use super::*;
     use component_snapshots::{
         ProcessingParams,
-        effect::{generate_basic_snapshot, generate_snapshot_with_reset},
+        effect::{generate_basic_snapshot, generate_snapshot, generate_snapshot_with_reset},
     };
-    use conformal_component::{ProcessingMode, audio::all_approx_eq};
+    use conformal_component::{ProcessingMode, audio::all_approx_eq, parameters::InternalValue};
     use snapshots::assert_snapshot;
 
     #[test]
@@ -188,4 +264,88 @@ mod tests {
             generate_basic_snapshot(&Component {}, &test_sig, &HashMap::new())
         );
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_sweep_offline() {
+        let test_sig: Vec<_> = dsp::test_utils::linear_sine_sweep(48000, 48000., 10., 20000.)
+            .iter()
+            .map(|x| x * 1. / 4.)
+            .collect();
+        assert_snapshot!(
+            "sweep_offline",
+            48000,
+            generate_snapshot(
+                &Component {},
+                &test_sig,
+                &ProcessingParams {
+                    processing_mode: ProcessingMode::Offline,
+                    ..Default::default()
+                },
+                &HashMap::new()
+            )
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_flanger_sweep() {
+        let test_sig: Vec<_> = dsp::test_utils::linear_sine_sweep(48000, 48000., 10., 20000.)
+            .iter()
+            .map(|x| x * 1. / 10.)
+            .collect();
+        assert_snapshot!(
+            "flanger_sweep",
+            48000,
+            generate_basic_snapshot(
+                &Component {},
+                &test_sig,
+                &HashMap::from([
+                    ("routing", InternalValue::Enum(6)),
+                    ("feedback", InternalValue::Numeric(70.)),
+                ])
+            )
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_vintage() {
+        let test_sig: Vec<_> = dsp::test_utils::sine(48000, 440. / 48000.)
+            .iter()
+            .map(|x| x * 1. / 3.)
+            .collect();
+        assert_snapshot!(
+            "vintage",
+            48000,
+            generate_basic_snapshot(
+                &Component {},
+                &test_sig,
+                &HashMap::from([("vintage", InternalValue::Numeric(100.))])
+            )
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshot_vibrato() {
+        let test_sig: Vec<_> = dsp::test_utils::sine(48000, 440. / 48000.)
+            .iter()
+            .map(|x| x * 1. / 3.)
+            .collect();
+        assert_snapshot!(
+            "vibrato",
+            48000,
+            generate_basic_snapshot(
+                &Component {},
+                &test_sig,
+                &HashMap::from([
+                    ("routing", InternalValue::Enum(2)),
+                    ("mix_mode", InternalValue::Enum(1)),
+                    ("lfo_shape", InternalValue::Enum(1)),
+                    ("rate", InternalValue::Numeric(5.)),
+                ])
+            )
+        );
+    }
 }
@@ -19,6 +19,12 @@ pub struct Options {
     /// and the delay must never be less than the lookaround (which is measured in samples).
     pub lookaround: u16,
 
+    /// The number of fractional delay phases in the interpolation kernel.
+    ///
+    /// More phases reduce the error from quantizing the fractional part of the
+    /// delay, at the cost of memory.
+    pub num_phases: u16,
+
     /// The maximum delay in samples.
     pub max_delay: usize,
 
@@ -67,6 +73,7 @@ impl ModulatedDelay {
     pub fn new(
         Options {
             lookaround,
+            num_phases,
             max_delay,
             max_samples_per_process_call,
         }: Options,
@@ -75,10 +82,9 @@ impl ModulatedDelay {
         // attenuating high frequencies (which are likely inaudible anyways)
         const BANDWIDTH: f32 = 0.85;
 
-        let length_per_phase = lookaround * 2 + 1;
-        let num_phases: u16 = 1025;
+        let length_per_phase = usize::from(lookaround) * 2 + 1;
         let mut kernel = kernel::lpf(kernel::LpfOptions {
-            length: length_per_phase * num_phases,
+            length: length_per_phase * usize::from(num_phases),
             increment: BANDWIDTH * 0.5 / f32::from(num_phases),
         });
         for tap in &mut kernel {
@@ -131,6 +137,7 @@ mod tests {
     pub fn fractional_offset_does_not_change_tuning() {
         let mut variable_delay = ModulatedDelay::new(Options {
             lookaround: 1,
+            num_phases: 1025,
             max_delay: 3,
             max_samples_per_process_call: 4096,
         });
@@ -162,6 +169,7 @@ mod tests {
         assert!(min_delay_samples >= lookaround as usize);
         let mut variable_delay = ModulatedDelay::new(Options {
             lookaround,
+            num_phases: 1025,
             max_delay: max_delay_samples,
             max_samples_per_process_call: 512,
         });
@@ -6,15 +6,42 @@
 const A: f32 = 1. / 8. / 32.;
 const B: f32 = 1. / 18. / 32.;
 
-pub fn nonlinearity(x: f32) -> f32 {
+/// The number of stages `A` and `B` were tuned for.
+const REFERENCE_STAGES: f32 = 256.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Coefficients {
+    a: f32,
+    b: f32,
+}
+
+impl Coefficients {
+    /// Each stage of the BBD adds a little distortion, so longer devices are
+    /// more nonlinear.
+    pub fn for_stages(stages: f32) -> Self {
+        let scale = stages / REFERENCE_STAGES;
+        Self {
+            a: A * scale,
+            b: B * scale,
+        }
+    }
+}
+
+impl Default for Coefficients {
+    fn default() -> Self {
+        Self::for_stages(REFERENCE_STAGES)
+    }
+}
+
+pub fn nonlinearity(x: f32, Coefficients { a, b }: Coefficients) -> f32 {
     if x > 1. {
-        x - A - B
+        x - a - b
     } else if x < -1. {
-        x - A + B
+        x - a + b
     } else {
         let x2 = x * x;
         // Note that the paper has a spurious "+A" here, but we don't want to add a DC offset
         // and adding this would make the clipping discontinuous, so likely the paper has a typo.
-        x - A * x2 - B * x2 * x
+        x - a * x2 - b * x2 * x
     }
 }
@@ -0,0 +1,61 @@
+//! Renders the algorithmic reverb to a stereo impulse response file, for A/B comparisons
+//! against the convolution engine or other reverbs.
+//!
+//! Usage: `cargo run -p reverb_component --example render_impulse_response -- out.wav [param=value ...]`
+//!
+//! Each `param` is a parameter id, and `value` is a number for numeric parameters, an index
+//! for enum parameters, or `on`/`off` for switches.
+
+use std::collections::HashMap;
+
+use conformal_component::Component as _;
+use conformal_component::audio::ChannelLayout;
+use conformal_component::parameters::{Info, InternalValue, TypeSpecificInfo};
+use reverb_component::{Component, render_impulse_response};
+
+const SAMPLING_RATE: i32 = 48000;
+const LENGTH_SECONDS: usize = 10;
+
+fn parse_override<'a>(infos: &[Info], arg: &'a str) -> (&'a str, InternalValue) {
+    let (id, value) = arg
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Expected param=value, got {arg}"));
+    let info = infos
+        .iter()
+        .find(|info| info.unique_id == id)
+        .unwrap_or_else(|| panic!("Unknown parameter {id}"));
+    let value = match info.type_specific {
+        TypeSpecificInfo::Enum { .. } => {
+            InternalValue::Enum(value.parse().expect("Expected an enum index"))
+        }
+        TypeSpecificInfo::Numeric { .. } => {
+            InternalValue::Numeric(value.parse().expect("Expected a number"))
+        }
+        TypeSpecificInfo::Switch { .. } => InternalValue::Switch(value == "on"),
+    };
+    (id, value)
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (path, overrides) = args.split_first().expect("Expected an output path");
+    let infos = Component::new().parameter_infos();
+    let overrides = overrides
+        .iter()
+        .map(|arg| parse_override(&infos, arg))
+        .collect::<HashMap<_, _>>();
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+    let channels = render_impulse_response(
+        &overrides,
+        SAMPLING_RATE as f32,
+        ChannelLayout::Stereo,
+        LENGTH_SECONDS * SAMPLING_RATE as usize,
+    );
+    let interleaved = channels[0]
+        .iter()
+        .zip(&channels[1])
+        .flat_map(|(left, right)| [*left, *right])
+        .collect::<Vec<_>>();
+    wavers::write(path, &interleaved, SAMPLING_RATE, 2).unwrap();
+}
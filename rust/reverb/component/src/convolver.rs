@@ -0,0 +1,245 @@
+//! Uniformly partitioned FFT convolution with no added latency.
+//!
+//! The first block of the impulse response is convolved directly in the time domain,
+//! and the rest is split into blocks that are convolved in the frequency domain with
+//! overlap-save. Since each frequency domain block is only heard a block after its
+//! input, the tail is always ready in time.
+
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+pub struct Convolver {
+    block_size: usize,
+
+    /// The first `block_size` samples of the impulse response.
+    head: Vec<f32>,
+
+    /// Spectra of the rest of the impulse response, one per block, pre-scaled to
+    /// undo the gain of the forward and inverse transforms.
+    tail: Vec<Vec<Complex<f32>>>,
+
+    /// Spectra of the most recent input blocks, one per tail block.
+    input_spectra: Vec<Vec<Complex<f32>>>,
+    input_spectra_pos: usize,
+
+    /// The previous input block followed by the current one.
+    input: Vec<f32>,
+
+    /// Position of the next sample in the current block.
+    position: usize,
+
+    /// Output of the tail blocks for the current block.
+    output: Vec<f32>,
+
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    fft_input: Vec<f32>,
+    fft_output: Vec<f32>,
+    accumulator: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Convolver {
+    /// Here `block_size` sets the trade-off between the cost of the direct convolution of
+    /// the first block, and the cost of the FFTs for the rest.
+    ///
+    /// Note that this allocates, so should not be called on the audio thread.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(impulse_response: &[f32], block_size: usize) -> Self {
+        let fft_size = 2 * block_size;
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+        let mut scratch =
+            vec![Complex::default(); forward.get_scratch_len().max(inverse.get_scratch_len())];
+
+        let (head, rest) = impulse_response.split_at(impulse_response.len().min(block_size));
+        let tail = rest
+            .chunks(block_size)
+            .map(|block| {
+                let mut padded = forward.make_input_vec();
+                for (padded, x) in padded.iter_mut().zip(block) {
+                    *padded = x / fft_size as f32;
+                }
+                let mut spectrum = forward.make_output_vec();
+                forward
+                    .process_with_scratch(&mut padded, &mut spectrum, &mut scratch)
+                    .unwrap();
+                spectrum
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            block_size,
+            head: head.to_vec(),
+            input_spectra: vec![forward.make_output_vec(); tail.len()],
+            tail,
+            input_spectra_pos: 0,
+            input: vec![0.0; fft_size],
+            position: 0,
+            output: vec![0.0; block_size],
+            fft_input: forward.make_input_vec(),
+            fft_output: inverse.make_output_vec(),
+            accumulator: forward.make_output_vec(),
+            scratch,
+            forward,
+            inverse,
+        }
+    }
+
+    fn process_block(&mut self) {
+        let blocks = self.tail.len();
+        self.fft_input.copy_from_slice(&self.input);
+        self.forward
+            .process_with_scratch(
+                &mut self.fft_input,
+                &mut self.input_spectra[self.input_spectra_pos],
+                &mut self.scratch,
+            )
+            .unwrap();
+
+        // Each tail block is convolved with the input from as many blocks ago as its
+        // position in the impulse response.
+        self.accumulator.fill(Complex::default());
+        for (age, block) in self.tail.iter().enumerate() {
+            let spectrum = &self.input_spectra[(self.input_spectra_pos + blocks - age) % blocks];
+            for (acc, (h, x)) in self.accumulator.iter_mut().zip(block.iter().zip(spectrum)) {
+                *acc += h * x;
+            }
+        }
+        self.input_spectra_pos = (self.input_spectra_pos + 1) % blocks;
+
+        // These are already real in theory, but the inverse transform checks.
+        if let Some(dc) = self.accumulator.first_mut() {
+            dc.im = 0.0;
+        }
+        if let Some(nyquist) = self.accumulator.last_mut() {
+            nyquist.im = 0.0;
+        }
+        self.inverse
+            .process_with_scratch(
+                &mut self.accumulator,
+                &mut self.fft_output,
+                &mut self.scratch,
+            )
+            .unwrap();
+
+        // With overlap-save, only the second half of the output is free of wrap-around.
+        self.output
+            .copy_from_slice(&self.fft_output[self.block_size..]);
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let newest = self.block_size + self.position;
+        self.input[newest] = input;
+        let head = self
+            .head
+            .iter()
+            .zip(self.input[..=newest].iter().rev())
+            .map(|(h, x)| h * x)
+            .sum::<f32>();
+        let output = head + self.output[self.position];
+
+        self.position += 1;
+        if self.position == self.block_size {
+            self.position = 0;
+            if !self.tail.is_empty() {
+                self.process_block();
+            }
+            self.input.copy_within(self.block_size.., 0);
+        }
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        for spectrum in &mut self.input_spectra {
+            spectrum.fill(Complex::default());
+        }
+        self.position = 0;
+        self.input_spectra_pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn direct_convolution(impulse_response: &[f32], input: &[f32]) -> Vec<f32> {
+        (0..input.len())
+            .map(|n| {
+                impulse_response
+                    .iter()
+                    .take(n + 1)
+                    .enumerate()
+                    .map(|(k, h)| h * input[n - k])
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn check_matches_direct(impulse_response_len: usize, block_size: usize) {
+        let impulse_response = dsp::test_utils::white_noise(impulse_response_len);
+        let input = dsp::test_utils::white_noise(2000);
+        let mut convolver = Convolver::new(&impulse_response, block_size);
+        let output = input
+            .iter()
+            .map(|x| convolver.process(*x))
+            .collect::<Vec<_>>();
+        for (a, b) in output
+            .iter()
+            .zip(direct_convolution(&impulse_response, &input))
+        {
+            assert_approx_eq!(a, b, 1e-3);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn short_impulse_response_matches_direct() {
+        check_matches_direct(20, 64);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn long_impulse_response_matches_direct() {
+        check_matches_direct(1000, 64);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn whole_blocks_match_direct() {
+        check_matches_direct(512, 128);
+    }
+
+    #[test]
+    fn empty_impulse_response_is_silent() {
+        let mut convolver = Convolver::new(&[], 16);
+        for x in dsp::test_utils::white_noise(100) {
+            assert_approx_eq!(convolver.process(x), 0.0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn reset() {
+        let mut convolver = Convolver::new(&dsp::test_utils::white_noise(300), 32);
+        let test_sig = dsp::test_utils::white_noise(200);
+        let before = test_sig
+            .iter()
+            .map(|x| convolver.process(*x))
+            .collect::<Vec<_>>();
+        convolver.reset();
+        let after = test_sig
+            .iter()
+            .map(|x| convolver.process(*x))
+            .collect::<Vec<_>>();
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert_approx_eq!(a, b);
+        }
+    }
+}
@@ -3,30 +3,56 @@ use rand::Rng;
 pub use crate::shuffler::CHANNELS;
 use crate::{multi_channel_per_sample_delay::MultiChannelPerSampleDelay, shuffler::Shuffler};
 
+/// Scales a delay by `size`. The result is fractional, so size changes sweep smoothly.
+///
+/// We never go below a single sample of delay.
+#[allow(clippy::cast_precision_loss)]
+pub fn scale_delay(delay: usize, size: f32) -> f32 {
+    (delay as f32 * size).max(1.0)
+}
+
+/// The line length needed to read `delay` scaled by up to `max_size`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn max_scaled_delay(delay: usize, max_size: f32) -> usize {
+    scale_delay(delay, max_size).ceil() as usize
+}
+
 #[derive(Debug, Clone)]
 struct DiffuserBlock {
     shuffler: Shuffler,
+    delays: [usize; CHANNELS],
     delay: MultiChannelPerSampleDelay<CHANNELS>,
 }
 
 impl DiffuserBlock {
-    fn new(rng: &mut impl Rng, max_delay: usize) -> Self {
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn new(rng: &mut impl Rng, max_delay: usize, max_size: f32) -> Self {
         // We ensure that each max_delay / CHANNELS section gets at least one channel of delay.
+        // We pick a position within each section rather than a sample count, so the same
+        // seed gives equivalent delays at any sampling rate.
+        let section = max_delay as f32 / CHANNELS as f32;
         let mut delays = [0; CHANNELS];
         for (i, delay) in delays.iter_mut().enumerate() {
-            let range_start = i * (max_delay / CHANNELS);
-            let range_end = range_start + (max_delay / CHANNELS);
-            *delay = rng.gen_range(range_start..range_end);
+            *delay = ((i as f32 + rng.gen_range(0.0..1.0f32)) * section) as usize;
         }
 
         Self {
             shuffler: Shuffler::new(rng),
-            delay: MultiChannelPerSampleDelay::new(delays),
+            delays,
+            delay: MultiChannelPerSampleDelay::new(delays.map(|d| max_scaled_delay(d, max_size))),
         }
     }
 
-    fn process(&mut self, input: &[f32; CHANNELS]) -> [f32; CHANNELS] {
-        let ret = self.shuffler.shuffle(&self.delay.read());
+    fn process(&mut self, input: &[f32; CHANNELS], size: f32) -> [f32; CHANNELS] {
+        let ret = self.shuffler.shuffle(
+            &self
+                .delay
+                .read_delayed(&self.delays.map(|d| scale_delay(d, size))),
+        );
         self.delay.write(input);
         ret
     }
@@ -95,11 +121,16 @@ fn density_weight_for_block(density: f32, block: usize) -> f32 {
 }
 
 trait ErCalc {
+    fn zero() -> Self;
     fn calc(early: f32, input: &[f32; CHANNELS], block: usize) -> Self;
     fn add_assign(&mut self, other: Self);
 }
 
 impl ErCalc for f32 {
+    fn zero() -> Self {
+        0.0
+    }
+
     fn calc(early: f32, input: &[f32; CHANNELS], block: usize) -> Self {
         er_weight_for_block(early, block) * input.iter().sum::<f32>()
     }
@@ -109,50 +140,51 @@ impl ErCalc for f32 {
     }
 }
 
-impl ErCalc for [f32; 2] {
+impl<const N: usize> ErCalc for [f32; N] {
+    fn zero() -> Self {
+        [0.0; N]
+    }
+
     fn calc(early: f32, input: &[f32; CHANNELS], block: usize) -> Self {
         let weight = er_weight_for_block(early, block);
-        // Split the evens and odds
-        [
+        // Each output takes every `N`th channel, so outputs don't share any channels.
+        core::array::from_fn(|output| {
             input
                 .iter()
                 .enumerate()
-                .filter_map(|(i, &v)| if i % 2 == 0 { Some(v) } else { None })
+                .filter_map(|(i, &v)| if i % N == output { Some(v) } else { None })
                 .sum::<f32>()
-                * weight,
-            input
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &v)| if i % 2 == 1 { Some(v) } else { None })
-                .sum::<f32>()
-                * weight,
-        ]
+                * weight
+        })
     }
 
     fn add_assign(&mut self, other: Self) {
-        self[0] += other[0];
-        self[1] += other[1];
+        for (a, b) in self.iter_mut().zip(other) {
+            *a += b;
+        }
     }
 }
 
 impl Diffuser {
-    pub fn new(rng: &mut impl Rng, max_delays: [usize; BLOCKS]) -> Self {
+    /// Here `max_size` is the largest `size` that will be passed to `process_multichannel`.
+    pub fn new(rng: &mut impl Rng, max_delays: [usize; BLOCKS], max_size: f32) -> Self {
         Self {
-            blocks: core::array::from_fn(|i| DiffuserBlock::new(rng, max_delays[i])),
+            blocks: core::array::from_fn(|i| DiffuserBlock::new(rng, max_delays[i], max_size)),
         }
     }
 
-    fn process<T: ErCalc + Default>(
+    fn process<T: ErCalc>(
         &mut self,
         early: f32,
         density: f32,
+        size: f32,
         input: &[f32; CHANNELS],
     ) -> ([f32; CHANNELS], T) {
         let mut block_output = *input;
         let mut actual_output = [0.0; CHANNELS];
-        let mut er = T::default();
+        let mut er = T::zero();
         for (i, block) in self.blocks.iter_mut().enumerate() {
-            block_output = block.process(&block_output);
+            block_output = block.process(&block_output, size);
             er.add_assign(T::calc(early, &block_output, i));
             let density = density_weight_for_block(density, i);
             if density > 0.0 {
@@ -166,29 +198,24 @@ impl Diffuser {
 
     /// Process one frame (one sample per channel) through the diffuser
     ///
-    /// Yields fully diffuse multichannel input for feedback section and a single
-    /// sample of early reflections. `early` must be between 0 and 1. When `early`
+    /// Yields fully diffuse multichannel input for feedback section and `N` channels
+    /// of early reflections. `early` must be between 0 and 1. When `early`
     /// is 0, the early reflections are not very diffuse but are quite early.
     /// When `early` is 1, the early reflections are more diffuse but less early.
-    pub fn process_mono(
-        &mut self,
-        early: f32,
-        density: f32,
-        input: &[f32; CHANNELS],
-    ) -> ([f32; CHANNELS], f32) {
-        self.process::<f32>(early, density, input)
-    }
-
-    /// Process one frame (one sample per channel) through the diffuser with stereo early reflections.
     ///
-    /// Yields fully diffuse multichannel input for feedback section and a stereo early
-    pub fn process_stereo(
+    /// Each early reflection channel is made of a distinct set of diffuser channels, so
+    /// they will be decorrelated.
+    ///
+    /// `size` scales all the diffuser delays, and must be no larger than the `max_size`
+    /// the diffuser was created with.
+    pub fn process_multichannel<const N: usize>(
         &mut self,
         early: f32,
         density: f32,
+        size: f32,
         input: &[f32; CHANNELS],
-    ) -> ([f32; CHANNELS], [f32; 2]) {
-        self.process::<[f32; 2]>(early, density, input)
+    ) -> ([f32; CHANNELS], [f32; N]) {
+        self.process::<[f32; N]>(early, density, size, input)
     }
 
     pub fn reset(&mut self) {
@@ -214,6 +241,7 @@ mod tests {
         let mut diffuser = Diffuser::new(
             &mut Xoshiro256PlusPlus::seed_from_u64(369),
             DELAYS_MS.map(|d| (d / 1000.0 * SAMPLING_RATE).round() as usize),
+            1.0,
         );
         let mut output = vec![0.0; SNAPSHOT_LENGTH];
         output[0] = run(&mut diffuser, 1.0);
@@ -227,7 +255,9 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn impulse_response() {
         impulse_response_snapshot_test("impulse_response", |diffuser, input| {
-            diffuser.process_mono(0.0, 1.0, &[input; CHANNELS]).0[0]
+            diffuser
+                .process_multichannel::<1>(0.0, 1.0, 1.0, &[input; CHANNELS])
+                .0[0]
         });
     }
 
@@ -235,7 +265,9 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn impulse_response_er_low() {
         impulse_response_snapshot_test("er_low", |diffuser, input| {
-            diffuser.process_mono(0.0, 1.0, &[input; CHANNELS]).1
+            diffuser
+                .process_multichannel::<1>(0.0, 1.0, 1.0, &[input; CHANNELS])
+                .1[0]
         });
     }
 
@@ -243,7 +275,9 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn impulse_response_er_high() {
         impulse_response_snapshot_test("er_high", |diffuser, input| {
-            diffuser.process_mono(1.0, 1.0, &[input; CHANNELS]).1
+            diffuser
+                .process_multichannel::<1>(1.0, 1.0, 1.0, &[input; CHANNELS])
+                .1[0]
         });
     }
 
@@ -251,7 +285,9 @@ mod tests {
     #[cfg_attr(miri, ignore)]
     fn impulse_response_low_density() {
         impulse_response_snapshot_test("impulse_low_density", |diffuser, input| {
-            diffuser.process_mono(0.0, 0.0, &[input; CHANNELS]).0[0]
+            diffuser
+                .process_multichannel::<1>(0.0, 0.0, 1.0, &[input; CHANNELS])
+                .0[0]
         });
     }
 }
@@ -0,0 +1,121 @@
+//! Ducks the wet signal while the dry input is active.
+
+/// The detected level at which the wet signal is fully ducked.
+const FULL_DUCK_LEVEL: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    /// How much to duck, from 0 (no ducking) to 1 (fully silence the wet signal).
+    pub amount: f32,
+
+    /// Time constant for the level to rise, in seconds.
+    pub attack: f32,
+
+    /// Time constant for the level to fall, in seconds.
+    pub release: f32,
+}
+
+/// Peak level follower with separate attack and release time constants.
+#[derive(Debug, Clone, Default)]
+pub struct Ducker {
+    level: f32,
+}
+
+/// Like `compander::PeakLevelDetector` in rchorus, we don't bother pre-warping.
+fn coeff(time: f32, sampling_rate: f32) -> f32 {
+    1. / (time * sampling_rate + 1.)
+}
+
+impl Ducker {
+    /// Follows the level of `input`, returning the gain to apply to the wet signal.
+    pub fn process(&mut self, input: f32, params: &Params, sampling_rate: f32) -> f32 {
+        let rectified = input.abs();
+        self.level += if rectified > self.level {
+            coeff(params.attack, sampling_rate)
+        } else {
+            coeff(params.release, sampling_rate)
+        } * (rectified - self.level);
+        1.0 - params.amount * (self.level / FULL_DUCK_LEVEL).min(1.0)
+    }
+
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    const SAMPLING_RATE: f32 = 48000.0;
+
+    const PARAMS: Params = Params {
+        amount: 1.0,
+        attack: 0.01,
+        release: 0.25,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn ducks_while_input_is_loud() {
+        let mut ducker = Ducker::default();
+        let gain = dsp::test_utils::sine(4800, 1123. / SAMPLING_RATE)
+            .iter()
+            .map(|x| ducker.process(*x, &PARAMS, SAMPLING_RATE))
+            .last()
+            .unwrap();
+        assert_approx_eq!(gain, 0.0, 1e-6);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn recovers_after_release() {
+        let mut ducker = Ducker::default();
+        for x in dsp::test_utils::sine(4800, 1123. / SAMPLING_RATE) {
+            ducker.process(x, &PARAMS, SAMPLING_RATE);
+        }
+        let mut silence =
+            std::iter::repeat_n(0.0, 96000).map(|x| ducker.process(x, &PARAMS, SAMPLING_RATE));
+        // After two release time constants, we should still be partially ducked.
+        let partial = silence.nth(24000).unwrap();
+        assert!(partial > 0.0 && partial < 0.9);
+        assert_approx_eq!(silence.last().unwrap(), 1.0, 0.05);
+    }
+
+    #[test]
+    fn no_ducking_at_zero_amount() {
+        let mut ducker = Ducker::default();
+        for x in dsp::test_utils::white_noise(100) {
+            assert_approx_eq!(
+                ducker.process(
+                    x,
+                    &Params {
+                        amount: 0.0,
+                        ..PARAMS
+                    },
+                    SAMPLING_RATE
+                ),
+                1.0
+            );
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut ducker = Ducker::default();
+        let test_sig = dsp::test_utils::sine(100, 440. / SAMPLING_RATE);
+        let before = test_sig
+            .iter()
+            .map(|x| ducker.process(*x, &PARAMS, SAMPLING_RATE))
+            .collect::<Vec<_>>();
+        ducker.reset();
+        let after = test_sig
+            .iter()
+            .map(|x| ducker.process(*x, &PARAMS, SAMPLING_RATE))
+            .collect::<Vec<_>>();
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert_approx_eq!(a, b);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Envelope for the gated reverb mode.
+
+use num_derive::FromPrimitive;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// The tail decays naturally.
+    #[default]
+    Natural,
+
+    /// The tail is held open while the input is active, then abruptly closed.
+    Gated,
+
+    /// The tail is played back in reversed segments, so each one swells in, then
+    /// abruptly closes. See `Reverser`.
+    Reverse,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    pub mode: Mode,
+
+    /// How long the gate stays open after the input drops, in seconds.
+    pub hold: f32,
+
+    /// How long the gate takes to close, in seconds.
+    pub release: f32,
+
+    /// Input level above which the gate is triggered, as a linear gain.
+    pub threshold: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum Stage {
+    #[default]
+    Closed,
+    Open {
+        samples: f32,
+    },
+    Release {
+        value: f32,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Gate {
+    stage: Stage,
+}
+
+impl Gate {
+    /// Returns the gain to apply to the wet signal, given the level of the dry input.
+    ///
+    /// This is always unity outside of gated mode.
+    pub fn process(&mut self, level: f32, params: &Params, sampling_rate: f32) -> f32 {
+        let triggered = level > params.threshold;
+        let hold_samples = params.hold * sampling_rate;
+        let release_incr = 1.0 / (params.release * sampling_rate).max(1.0);
+        let (gain, stage) = match (params.mode, self.stage) {
+            (Mode::Natural | Mode::Reverse, _) => (1.0, Stage::Closed),
+            // Any input re-opens the gate and restarts the hold.
+            (Mode::Gated, _) if triggered => (1.0, Stage::Open { samples: 0.0 }),
+            (Mode::Gated, Stage::Closed) => (0.0, Stage::Closed),
+            (Mode::Gated, Stage::Open { samples }) if samples >= hold_samples => {
+                (1.0, Stage::Release { value: 1.0 })
+            }
+            (Mode::Gated, Stage::Open { samples }) => (
+                1.0,
+                Stage::Open {
+                    samples: samples + 1.0,
+                },
+            ),
+            (Mode::Gated, Stage::Release { value }) => {
+                let value = value - release_incr;
+                if value <= 0.0 {
+                    (0.0, Stage::Closed)
+                } else {
+                    (value, Stage::Release { value })
+                }
+            }
+        };
+        self.stage = stage;
+        gain
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = Stage::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    const SAMPLING_RATE: f32 = 1000.0;
+
+    fn run(mode: Mode, input: impl IntoIterator<Item = f32>) -> Vec<f32> {
+        let mut gate = Gate::default();
+        let params = Params {
+            mode,
+            hold: 0.1,
+            release: 0.01,
+            threshold: 0.01,
+        };
+        input
+            .into_iter()
+            .map(|x| gate.process(x, &params, SAMPLING_RATE))
+            .collect()
+    }
+
+    fn all_near(x: &[f32], value: f32) -> bool {
+        x.iter().all(|x| (x - value).abs() < 1e-6)
+    }
+
+    fn burst(length: usize) -> impl Iterator<Item = f32> {
+        std::iter::repeat_n(1.0, length).chain(std::iter::repeat_n(0.0, 1000))
+    }
+
+    #[test]
+    fn natural_is_unity() {
+        assert!(all_near(&run(Mode::Natural, burst(10)), 1.0));
+    }
+
+    #[test]
+    fn gated_holds_after_input() {
+        let output = run(Mode::Gated, burst(50));
+        assert!(all_near(&output[..151], 1.0));
+        assert!(output[151..160].windows(2).all(|w| w[1] < w[0]));
+        assert!(all_near(&output[161..], 0.0));
+    }
+
+    #[test]
+    fn gated_stays_closed_without_input() {
+        assert!(all_near(
+            &run(Mode::Gated, std::iter::repeat_n(0.0, 100)),
+            0.0
+        ));
+    }
+
+    #[test]
+    fn reverse_is_unity() {
+        assert!(all_near(&run(Mode::Reverse, burst(10)), 1.0));
+    }
+
+    #[test]
+    fn quiet_input_does_not_trigger() {
+        let mut gate = Gate::default();
+        let params = Params {
+            mode: Mode::Gated,
+            hold: 0.1,
+            release: 0.01,
+            threshold: 0.5,
+        };
+        assert_approx_eq!(gate.process(0.4, &params, SAMPLING_RATE), 0.0);
+        assert_approx_eq!(gate.process(0.6, &params, SAMPLING_RATE), 1.0);
+    }
+
+    #[test]
+    fn reset() {
+        let mut gate = Gate::default();
+        let params = Params {
+            mode: Mode::Gated,
+            hold: 0.1,
+            release: 0.01,
+            threshold: 0.01,
+        };
+        gate.process(1.0, &params, SAMPLING_RATE);
+        gate.reset();
+        assert_approx_eq!(gate.process(0.0, &params, SAMPLING_RATE), 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+//! Renders the algorithmic reverb as an impulse response, so it can be compared against
+//! the convolution engine or loaded into other convolution reverbs.
+
+use std::collections::HashMap;
+
+use conformal_component::audio::{Buffer, BufferData, BufferMut, ChannelLayout};
+use conformal_component::effect::{Effect as EffectTrait, ProcessContext};
+use conformal_component::parameters::{BufferStates, ConstantBufferStates, InternalValue};
+use conformal_component::{ProcessingEnvironment, ProcessingMode, Processor};
+
+use crate::{Effect, PARAMETERS};
+
+/// We render in blocks of this many samples.
+const BLOCK_SIZE: usize = 512;
+
+struct RenderContext<P> {
+    parameters: P,
+}
+
+impl<P: BufferStates> ProcessContext for RenderContext<P> {
+    fn parameters(&self) -> &impl BufferStates {
+        &self.parameters
+    }
+}
+
+/// Renders `num_frames` of the algorithmic reverb's response to an impulse in every
+/// input channel, returning one impulse response per channel of `channel_layout`.
+///
+/// `overrides` sets any parameters that differ from their defaults, as in the host.
+/// The result is always fully wet. Ducking, the gated and reverse modes, and freeze
+/// are disabled, since they can't be captured by an impulse response.
+///
+/// We render offline, so the result uses the higher quality interpolation.
+#[must_use]
+pub fn render_impulse_response<S: ::std::hash::BuildHasher>(
+    overrides: &HashMap<&str, InternalValue, S>,
+    sampling_rate: f32,
+    channel_layout: ChannelLayout,
+    num_frames: usize,
+) -> Vec<Vec<f32>> {
+    let mut overrides = overrides
+        .iter()
+        .map(|(id, value)| (*id, *value))
+        .collect::<HashMap<_, _>>();
+    overrides.extend([
+        ("bypass", InternalValue::Switch(false)),
+        ("mix", InternalValue::Numeric(100.0)),
+        ("duck_amount", InternalValue::Numeric(0.0)),
+        ("mode", InternalValue::Enum(0)),
+        ("freeze", InternalValue::Switch(false)),
+        ("engine", InternalValue::Enum(0)),
+    ]);
+    let context = RenderContext {
+        parameters: ConstantBufferStates::new_override_defaults(
+            PARAMETERS.iter().cloned(),
+            &overrides,
+        ),
+    };
+    let mut effect = Effect::new(&ProcessingEnvironment {
+        sampling_rate,
+        max_samples_per_process_call: BLOCK_SIZE,
+        channel_layout,
+        processing_mode: ProcessingMode::Offline,
+    });
+    effect.set_processing(true);
+
+    let num_channels = channel_layout.num_channels();
+    let mut output = (0..num_channels)
+        .map(|_| Vec::with_capacity(num_frames))
+        .collect::<Vec<_>>();
+    let mut start = 0;
+    while start < num_frames {
+        let block_size = BLOCK_SIZE.min(num_frames - start);
+        let mut input = BufferData::new(channel_layout, block_size);
+        if start == 0 {
+            for channel in 0..num_channels {
+                input.channel_mut(channel)[0] = 1.0;
+            }
+        }
+        let mut block = BufferData::new(channel_layout, block_size);
+        effect.process(&context, &input, &mut block);
+        for (channel, output) in output.iter_mut().enumerate() {
+            output.extend_from_slice(block.channel(channel));
+        }
+        start += block_size;
+    }
+    output
+}
@@ -4,24 +4,36 @@ use conformal_component::parameters::{self, Flags, InfoRef, TypeSpecificInfoRef}
 use conformal_component::pzip;
 use conformal_component::{Component as ComponentTrait, ProcessingEnvironment, Processor};
 use dsp::f32::lerp;
+use num_traits::FromPrimitive;
 use rtsan_standalone::nonblocking;
 
+mod convolver;
 mod diffuser;
+mod ducker;
+mod gate;
+mod impulse_response;
 mod multi_channel_feedback_loop;
 mod multi_channel_per_sample_delay;
 mod per_sample_delay;
 mod per_sample_modulated_delay;
+mod pitch_shifter;
 mod reverb;
+mod reverser;
 mod shuffler;
 
 const TIME_MIN: f32 = 0.7;
-const COEFF_MIN: f32 = 0.3;
-const TIME_MID: f32 = 1.2;
-const COEFF_MID: f32 = 0.55;
+const TIME_DEFAULT: f32 = 1.2;
 const TIME_MAX: f32 = 45.0;
-const COEFF_MAX: f32 = 0.97;
 
-const PARAMETERS: [InfoRef<'static, &'static str>; 7] = [
+const PRE_DELAY_MAX_MS: f32 = reverb::MAX_PRE_DELAY_SECONDS * 1000.0;
+const GATE_HOLD_MAX_MS: f32 = reverb::MAX_GATE_HOLD_SECONDS * 1000.0;
+
+pub use impulse_response::render_impulse_response;
+
+const SIZE_MIN: f32 = reverb::MIN_SIZE * 100.0;
+const SIZE_MAX: f32 = reverb::MAX_SIZE * 100.0;
+
+const PARAMETERS: [InfoRef<'static, &'static str>; 29] = [
     InfoRef {
         title: "Bypass",
         short_title: "Bypass",
@@ -68,11 +80,33 @@ const PARAMETERS: [InfoRef<'static, &'static str>; 7] = [
         unique_id: "time",
         flags: Flags { automatable: true },
         type_specific: TypeSpecificInfoRef::Numeric {
-            default: TIME_MID,
+            default: TIME_DEFAULT,
             valid_range: TIME_MIN..=TIME_MAX,
             units: Some("s"),
         },
     },
+    InfoRef {
+        title: "Bass Decay Multiplier",
+        short_title: "Bass Decay",
+        unique_id: "bass_multiplier",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 1.0,
+            valid_range: 0.5f32..=2.0,
+            units: Some("x"),
+        },
+    },
+    InfoRef {
+        title: "Bass Crossover",
+        short_title: "Bass Crossover",
+        unique_id: "bass_crossover",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 250.0,
+            valid_range: 50f32..=1000.0,
+            units: Some("Hz"),
+        },
+    },
     InfoRef {
         title: "Early Reflection Character",
         short_title: "Early Reflections",
@@ -95,6 +129,211 @@ const PARAMETERS: [InfoRef<'static, &'static str>; 7] = [
             units: Some("%"),
         },
     },
+    InfoRef {
+        title: "Low Cut",
+        short_title: "Low Cut",
+        unique_id: "low_cut",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: reverb::LOW_CUT_OFF,
+            valid_range: reverb::LOW_CUT_OFF..=2000.0,
+            units: Some("Hz"),
+        },
+    },
+    InfoRef {
+        title: "High Cut",
+        short_title: "High Cut",
+        unique_id: "high_cut",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: reverb::HIGH_CUT_OFF,
+            valid_range: 1000f32..=reverb::HIGH_CUT_OFF,
+            units: Some("Hz"),
+        },
+    },
+    InfoRef {
+        title: "Size",
+        short_title: "Size",
+        unique_id: "size",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 100.0,
+            valid_range: SIZE_MIN..=SIZE_MAX,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Width",
+        short_title: "Width",
+        unique_id: "width",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 100.0,
+            valid_range: 0f32..=200.0,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Mono Input",
+        short_title: "Mono Input",
+        unique_id: "mono_input",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Switch { default: false },
+    },
+    InfoRef {
+        title: "Pre-Delay",
+        short_title: "Pre-Delay",
+        unique_id: "pre_delay",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.0,
+            valid_range: 0f32..=PRE_DELAY_MAX_MS,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Freeze",
+        short_title: "Freeze",
+        unique_id: "freeze",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Switch { default: false },
+    },
+    InfoRef {
+        title: "Modulation Depth",
+        short_title: "Mod Depth",
+        unique_id: "modulation_depth",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 20.0,
+            valid_range: 0f32..=40.0,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Modulation Rate",
+        short_title: "Mod Rate",
+        unique_id: "modulation_rate",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.02,
+            valid_range: 0.01f32..=5.0,
+            units: Some("Hz"),
+        },
+    },
+    InfoRef {
+        title: "Modulate All Lines",
+        short_title: "Mod All",
+        unique_id: "modulate_all_lines",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Switch { default: false },
+    },
+    InfoRef {
+        title: "Shimmer",
+        short_title: "Shimmer",
+        unique_id: "shimmer",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.0,
+            valid_range: 0f32..=100.0,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Shimmer Interval",
+        short_title: "Interval",
+        unique_id: "shimmer_interval",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["+12", "+7", "-12"],
+        },
+    },
+    InfoRef {
+        title: "Ducking",
+        short_title: "Ducking",
+        unique_id: "duck_amount",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 0.0,
+            valid_range: 0f32..=100.0,
+            units: Some("%"),
+        },
+    },
+    InfoRef {
+        title: "Ducking Attack",
+        short_title: "Duck Attack",
+        unique_id: "duck_attack",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 10.0,
+            valid_range: 0.1f32..=100.0,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Ducking Release",
+        short_title: "Duck Release",
+        unique_id: "duck_release",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 250.0,
+            valid_range: 10f32..=2000.0,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Mode",
+        short_title: "Mode",
+        unique_id: "mode",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["Natural", "Gated", "Reverse"],
+        },
+    },
+    InfoRef {
+        title: "Gate Hold",
+        short_title: "Hold",
+        unique_id: "gate_hold",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 300.0,
+            valid_range: 10f32..=GATE_HOLD_MAX_MS,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Gate Release",
+        short_title: "Release",
+        unique_id: "gate_release",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: 50.0,
+            valid_range: 1f32..=1000.0,
+            units: Some("ms"),
+        },
+    },
+    InfoRef {
+        title: "Gate Threshold",
+        short_title: "Threshold",
+        unique_id: "gate_threshold",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Numeric {
+            default: -40.0,
+            valid_range: -80f32..=0.0,
+            units: Some("dB"),
+        },
+    },
+    InfoRef {
+        title: "Engine",
+        short_title: "Engine",
+        unique_id: "engine",
+        flags: Flags { automatable: true },
+        type_specific: TypeSpecificInfoRef::Enum {
+            default: 0,
+            values: &["Algorithmic", "Convolution"],
+        },
+    },
 ];
 
 const INTERNAL_MIX: [f32; 2] = [0.0, 1.0];
@@ -108,22 +347,6 @@ fn to_internal(value: f32, internal_range: [f32; 2]) -> f32 {
     lerp(internal_range[0], internal_range[1], ratio)
 }
 
-fn time_to_coeff(time: f32) -> f32 {
-    if time <= TIME_MIN {
-        lerp(
-            COEFF_MIN,
-            COEFF_MID,
-            (time - TIME_MIN) / (TIME_MID - TIME_MIN),
-        )
-    } else {
-        lerp(
-            COEFF_MID,
-            COEFF_MAX,
-            (time - TIME_MID) / (TIME_MAX - TIME_MID),
-        )
-    }
-}
-
 #[derive(Clone, Debug, Default)]
 pub struct Component {}
 
@@ -145,6 +368,14 @@ impl Effect {
             reverb: reverb::Reverb::new(env),
         }
     }
+
+    /// Loads the impulse response for the "Convolution" engine, with one slice per
+    /// channel. A mono impulse response is used for every channel.
+    ///
+    /// Note that this allocates, so should not be called on the audio thread.
+    pub fn load_impulse_response(&mut self, channels: &[impl AsRef<[f32]>]) {
+        self.reverb.load_impulse_response(channels);
+    }
 }
 
 impl Processor for Effect {
@@ -167,22 +398,72 @@ impl EffectTrait for Effect {
         input: &impl Buffer,
         output: &mut impl BufferMut,
     ) {
-        // Snapshot the parameters at the start of the buffer - we don't support per-sample automation.
-        if let Some(params) = pzip!(
-            context.parameters()[switch "bypass", numeric "mix", numeric "brightness", numeric "tone", numeric "time", numeric "early_reflections", numeric "density"]
+        let params = pzip!(
+            context.parameters()[switch "bypass", numeric "mix", numeric "brightness", numeric "tone", numeric "time", numeric "bass_multiplier", numeric "bass_crossover", numeric "early_reflections", numeric "density", numeric "low_cut", numeric "high_cut", numeric "size", numeric "width", switch "mono_input", numeric "pre_delay", switch "freeze", numeric "modulation_depth", numeric "modulation_rate", switch "modulate_all_lines", numeric "shimmer", enum "shimmer_interval", numeric "duck_amount", numeric "duck_attack", numeric "duck_release", enum "mode", numeric "gate_hold", numeric "gate_release", numeric "gate_threshold", enum "engine"]
         )
-        .map(|(bypass, mix, brightness, tone, time, early_reflections, density)| reverb::Params {
-            mix: if bypass { 0.0 } else { to_internal(mix, INTERNAL_MIX) },
-            brightness: to_internal(brightness, INTERNAL_BRIGHTNESS),
-            damping: to_internal(tone, INTERNAL_DAMPING),
-            feedback: time_to_coeff(time),
-            early_reflections: to_internal(early_reflections, INTERNAL_EARLY_REFLECTIONS),
-            density: to_internal(density, INTERNAL_DENSITY),
-        })
-        .next()
-        {
-            self.reverb.process(&params, input, output);
-        }
+        .map(
+            |(
+                bypass,
+                mix,
+                brightness,
+                tone,
+                time,
+                bass_multiplier,
+                bass_crossover,
+                early_reflections,
+                density,
+                low_cut,
+                high_cut,
+                size,
+                width,
+                mono_input,
+                pre_delay,
+                freeze,
+                modulation_depth,
+                modulation_rate,
+                modulate_all_lines,
+                shimmer,
+                shimmer_interval,
+                duck_amount,
+                duck_attack,
+                duck_release,
+                mode,
+                gate_hold,
+                gate_release,
+                gate_threshold,
+                engine,
+            )| reverb::Params {
+                mix: if bypass { 0.0 } else { to_internal(mix, INTERNAL_MIX) },
+                pre_delay: pre_delay / 1000.0,
+                brightness: to_internal(brightness, INTERNAL_BRIGHTNESS),
+                damping: to_internal(tone, INTERNAL_DAMPING),
+                decay_time: time,
+                bass_multiplier,
+                bass_crossover,
+                early_reflections: to_internal(early_reflections, INTERNAL_EARLY_REFLECTIONS),
+                density: to_internal(density, INTERNAL_DENSITY),
+                low_cut,
+                high_cut,
+                size: size / 100.0,
+                width: width / 100.0,
+                mono_input,
+                modulation_depth: modulation_depth / 1000.0,
+                modulation_rate,
+                modulate_all_lines,
+                shimmer_amount: shimmer / 100.0,
+                shimmer_interval: FromPrimitive::from_u32(shimmer_interval).unwrap(),
+                duck_amount: duck_amount / 100.0,
+                duck_attack: duck_attack / 1000.0,
+                duck_release: duck_release / 1000.0,
+                mode: FromPrimitive::from_u32(mode).unwrap(),
+                gate_hold: gate_hold / 1000.0,
+                gate_release: gate_release / 1000.0,
+                gate_threshold: 10f32.powf(gate_threshold / 20.0),
+                freeze,
+                engine: FromPrimitive::from_u32(engine).unwrap(),
+            },
+        );
+        self.reverb.process(params, input, output);
     }
 }
 
@@ -204,9 +485,16 @@ mod tests {
 
     use component_snapshots::{
         ProcessingParams,
-        effect::{generate_basic_snapshot, generate_snapshot, generate_snapshot_with_reset},
+        effect::{
+            generate_basic_snapshot, generate_snapshot, generate_snapshot_with_params,
+            generate_snapshot_with_reset,
+        },
+    };
+    use conformal_component::{
+        ProcessingMode,
+        audio::{BufferData, ChannelLayout, all_approx_eq},
+        parameters::{BufferStates, ConstantBufferStates, InternalValue, RampedStatesMap},
     };
-    use conformal_component::{ProcessingMode, audio::all_approx_eq, parameters::InternalValue};
     use snapshots::assert_snapshot;
 
     use super::*;
@@ -252,6 +540,68 @@ mod tests {
         assert!(all_approx_eq(buff_512, buff_1024, 1e-6));
     }
 
+    struct TestProcessContext<P> {
+        parameters: P,
+    }
+
+    impl<P: BufferStates> ProcessContext for TestProcessContext<P> {
+        fn parameters(&self) -> &impl BufferStates {
+            &self.parameters
+        }
+    }
+
+    /// Process a sine while automating mix and time across the whole signal,
+    /// splitting it into buffers of at most `buffer_size`.
+    #[allow(clippy::cast_precision_loss)]
+    fn automated_snapshot(buffer_size: usize) -> Vec<f32> {
+        const LENGTH: usize = 9600;
+        let automation_at = |index: usize| {
+            let t = index as f32 / (LENGTH - 1) as f32;
+            HashMap::from([
+                ("mix", InternalValue::Numeric(lerp(0.0, 100.0, t))),
+                ("time", InternalValue::Numeric(lerp(TIME_MIN, 5.0, t))),
+            ])
+        };
+        let mut effect = Component::new().create_processor(&ProcessingEnvironment {
+            sampling_rate: 48000.0,
+            max_samples_per_process_call: buffer_size,
+            channel_layout: ChannelLayout::Mono,
+            processing_mode: ProcessingMode::Realtime,
+        });
+        effect.set_processing(true);
+        let input = dsp::test_utils::sine(LENGTH, 440. / 48000.);
+        let mut output = Vec::with_capacity(LENGTH);
+        for (index, chunk) in input.chunks(buffer_size).enumerate() {
+            let start = index * buffer_size;
+            let context = TestProcessContext {
+                parameters: RampedStatesMap::new(
+                    PARAMETERS.iter().cloned(),
+                    &automation_at(start),
+                    &automation_at(start + chunk.len() - 1),
+                    chunk.len(),
+                ),
+            };
+            let mut output_buffer = BufferData::new(ChannelLayout::Mono, chunk.len());
+            effect.process(
+                &context,
+                &BufferData::new_mono(chunk.to_vec()),
+                &mut output_buffer,
+            );
+            output.extend_from_slice(output_buffer.channel(0));
+        }
+        output
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn buffer_size_agnostic_under_automation() {
+        assert!(all_approx_eq(
+            automated_snapshot(100),
+            automated_snapshot(512),
+            1e-4
+        ));
+    }
+
     fn impulse_response_for_params(params: &HashMap<&'_ str, InternalValue>) -> Vec<f32> {
         const SNAPSHOT_LENGTH: usize = 48_000 * 2;
         let mut impulse_vec = vec![0.0; SNAPSHOT_LENGTH];
@@ -315,6 +665,32 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_bass_long() {
+        assert_snapshot!(
+            "impulse_bass_long",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("bass_multiplier", InternalValue::Numeric(2.0)),
+                ("bass_crossover", InternalValue::Numeric(400.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_bass_short() {
+        assert_snapshot!(
+            "impulse_bass_short",
+            48000,
+            impulse_response_for_params(&HashMap::from([(
+                "bass_multiplier",
+                InternalValue::Numeric(0.5)
+            )]))
+        );
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn impulse_early_reflections_late() {
@@ -363,4 +739,214 @@ mod tests {
             impulse_response_for_params(&HashMap::from([("density", InternalValue::Numeric(0.0))]))
         );
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_band_limited() {
+        assert_snapshot!(
+            "impulse_band_limited",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("low_cut", InternalValue::Numeric(300.0)),
+                ("high_cut", InternalValue::Numeric(5000.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_gated() {
+        assert_snapshot!(
+            "impulse_gated",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("mode", InternalValue::Enum(1)),
+                ("time", InternalValue::Numeric(5.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_reverse() {
+        assert_snapshot!(
+            "impulse_reverse",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("mode", InternalValue::Enum(2)),
+                ("time", InternalValue::Numeric(5.0)),
+                ("gate_hold", InternalValue::Numeric(500.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_small() {
+        assert_snapshot!(
+            "impulse_small",
+            48000,
+            impulse_response_for_params(&HashMap::from([(
+                "size",
+                InternalValue::Numeric(SIZE_MIN)
+            )]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_medium() {
+        assert_snapshot!(
+            "impulse_medium",
+            48000,
+            impulse_response_for_params(&HashMap::from([("size", InternalValue::Numeric(60.0))]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_large() {
+        assert_snapshot!(
+            "impulse_large",
+            48000,
+            impulse_response_for_params(&HashMap::from([(
+                "size",
+                InternalValue::Numeric(SIZE_MAX)
+            )]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_pre_delay() {
+        assert_snapshot!(
+            "impulse_pre_delay",
+            48000,
+            impulse_response_for_params(&HashMap::from([(
+                "pre_delay",
+                InternalValue::Numeric(250.0)
+            )]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_unmodulated() {
+        assert_snapshot!(
+            "impulse_unmodulated",
+            48000,
+            impulse_response_for_params(&HashMap::from([(
+                "modulation_depth",
+                InternalValue::Numeric(0.0)
+            )]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_modulate_all_lines() {
+        assert_snapshot!(
+            "impulse_modulate_all_lines",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("modulation_depth", InternalValue::Numeric(4.0)),
+                ("modulation_rate", InternalValue::Numeric(1.5)),
+                ("modulate_all_lines", InternalValue::Switch(true)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_shimmer() {
+        assert_snapshot!(
+            "impulse_shimmer",
+            48000,
+            impulse_response_for_params(&HashMap::from([
+                ("shimmer", InternalValue::Numeric(50.0)),
+                ("time", InternalValue::Numeric(5.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn convolution_matches_algorithmic() {
+        const IMPULSE_RESPONSE_LENGTH: usize = 48_000 * 2;
+        let params = HashMap::from([
+            ("mix", InternalValue::Numeric(100.0)),
+            // Modulation is time-varying, so can't be captured by an impulse response.
+            ("modulation_depth", InternalValue::Numeric(0.0)),
+            ("time", InternalValue::Numeric(0.7)),
+        ]);
+        let processing_params = ProcessingParams {
+            processing_mode: ProcessingMode::Offline,
+            ..Default::default()
+        };
+        let input = dsp::test_utils::white_noise(4800)
+            .into_iter()
+            .chain(std::iter::repeat_n(0.0, 43200))
+            .collect::<Vec<_>>();
+        let algorithmic = generate_snapshot(&Component::new(), &input, &processing_params, &params);
+
+        let mut effect = Effect::new(&ProcessingEnvironment {
+            sampling_rate: processing_params.sampling_rate,
+            max_samples_per_process_call: processing_params.max_buffer_size,
+            channel_layout: ChannelLayout::Mono,
+            processing_mode: processing_params.processing_mode,
+        });
+        effect.load_impulse_response(&render_impulse_response(
+            &params,
+            processing_params.sampling_rate,
+            ChannelLayout::Mono,
+            IMPULSE_RESPONSE_LENGTH,
+        ));
+        effect.set_processing(true);
+        let mut convolution_params = params.clone();
+        convolution_params.insert("engine", InternalValue::Enum(1));
+        let convolution = generate_snapshot_with_params(
+            &mut effect,
+            &input,
+            processing_params.max_buffer_size,
+            &ConstantBufferStates::new_override_defaults(
+                PARAMETERS.iter().cloned(),
+                &convolution_params,
+            ),
+        );
+        assert!(all_approx_eq(algorithmic, convolution, 1e-4));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn stereo_impulse_response_is_decorrelated() {
+        let impulse_response =
+            render_impulse_response(&HashMap::new(), 48000.0, ChannelLayout::Stereo, 48000);
+        let [left, right] = &impulse_response[..] else {
+            panic!("Expected two channels");
+        };
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        let correlation = dot(left, right) / (dot(left, left) * dot(right, right)).sqrt();
+        assert!(correlation.abs() < 0.5);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_offline() {
+        const SNAPSHOT_LENGTH: usize = 48_000 * 2;
+        let mut impulse_vec = vec![0.0; SNAPSHOT_LENGTH];
+        impulse_vec[0] = 1.0;
+        assert_snapshot!(
+            "impulse_offline",
+            48000,
+            generate_snapshot(
+                &Component::new(),
+                &impulse_vec,
+                &ProcessingParams {
+                    processing_mode: ProcessingMode::Offline,
+                    ..Default::default()
+                },
+                &HashMap::new(),
+            )
+        );
+    }
 }
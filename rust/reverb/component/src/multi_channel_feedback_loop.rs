@@ -1,75 +1,215 @@
-use crate::multi_channel_per_sample_delay::MultiChannelPerSampleDelay;
-use crate::{diffuser::CHANNELS, per_sample_modulated_delay::PerSampleModulatedDelay};
+use crate::{
+    diffuser::{CHANNELS, max_scaled_delay, scale_delay},
+    per_sample_modulated_delay::{Interpolation, PerSampleModulatedDelay},
+    pitch_shifter::PitchShifter,
+};
+use dsp::f32::lerp;
 use dsp::iir::svf::{GainInput, GainRawParams, Svf, calc_g, calc_two_r};
+use dsp::slew::{SlewLimiter, rate_from_time};
 
-const UNMODULATED_CHANNELS: usize = CHANNELS - 1;
+/// The channel that is modulated even when we aren't modulating all lines.
+const MODULATED_CHANNEL: usize = CHANNELS - 1;
 const FILTER_CHANNELS: usize = CHANNELS;
 
+/// How long it takes to fade modulation in or out of a line when `all_lines` changes.
+const ALL_LINES_RAMP_SECONDS: f32 = 0.05;
+
+/// Controls how the delay lines in the feedback loop are modulated.
+#[derive(Debug, Clone, Copy)]
+pub struct Modulation {
+    /// Depth of modulation in samples. 4ms is fine.
+    pub depth: f32,
+
+    /// Speed of modulation in cycles per sample. 6 hz is fine.
+    pub rate: f32,
+
+    /// If false, we only modulate a single delay line.
+    pub all_lines: bool,
+}
+
+/// Controls how the feedback loop loses energy at different frequencies.
+#[derive(Debug, Clone, Copy)]
+pub struct Damping {
+    /// The sqrt of the gain of the high shelf.
+    ///
+    /// So, lower values mean more damping!
+    pub high: f32,
+
+    /// How much longer low frequencies take to decay than the rest of the tail.
+    pub bass_multiplier: f32,
+
+    /// The crossover frequency below which `bass_multiplier` applies, in cycles per sample.
+    pub bass_crossover: f32,
+}
+
+/// Controls the pitch shifting in the feedback path.
+#[derive(Debug, Clone, Copy)]
+pub struct Shimmer {
+    /// How much of the feedback signal is pitch shifted, from 0 to 1.
+    pub amount: f32,
+
+    /// Ratio of the shifted frequency to the original, e.g. 2 for an octave up.
+    pub ratio: f32,
+}
+
+/// Spread the LFO phases evenly between lines so they are decorrelated.
+///
+/// The line that is always modulated starts at phase 0.
+#[allow(clippy::cast_precision_loss)]
+fn lfo_phase(channel: usize) -> f32 {
+    ((channel + 1) % CHANNELS) as f32 / CHANNELS as f32
+}
+
 pub struct MultiChannelFeedbackLoop {
-    delay: MultiChannelPerSampleDelay<UNMODULATED_CHANNELS>,
-    modulated_delay: PerSampleModulatedDelay,
+    delays: [PerSampleModulatedDelay; CHANNELS],
+
+    /// The length of each delay line at a `size` of 1.0, in samples.
+    delay_lengths: [usize; CHANNELS],
     shelf_g: f64,
     shelf_two_r: f64,
     shelf: [Svf; FILTER_CHANNELS],
+    low_shelf: [Svf; FILTER_CHANNELS],
+    shifters: [PitchShifter; CHANNELS],
+
+    /// How much of the modulation depth each line gets, from 0 to 1.
+    line_depths: [SlewLimiter; CHANNELS],
+    line_depth_rate: f32,
 }
 
 const SHELF_FREQ: f32 = 2000.0;
 const SHELF_Q: f64 = 0.707;
 
+/// We treat smaller feedback values as this when calculating the bass shelf, to
+/// avoid infinite gains.
+const MIN_BASS_FEEDBACK: f32 = 0.01;
+
 impl MultiChannelFeedbackLoop {
-    pub fn new(delay: [usize; CHANNELS], sampling_rate: f32) -> Self {
+    /// Here `max_size` is the largest `size` that will be passed to `process`.
+    pub fn new(
+        delay: [usize; CHANNELS],
+        sampling_rate: f32,
+        interpolation: Interpolation,
+        max_size: f32,
+    ) -> Self {
         Self {
-            delay: MultiChannelPerSampleDelay::new(
-                delay[..UNMODULATED_CHANNELS].try_into().unwrap(),
-            ),
-            modulated_delay: PerSampleModulatedDelay::new(delay[UNMODULATED_CHANNELS]),
+            delays: core::array::from_fn(|i| {
+                PerSampleModulatedDelay::new(
+                    max_scaled_delay(delay[i], max_size),
+                    interpolation,
+                    lfo_phase(i),
+                )
+            }),
+            delay_lengths: delay,
             shelf: core::array::from_fn(|_| Svf::default()),
+            low_shelf: core::array::from_fn(|_| Svf::default()),
+            shifters: core::array::from_fn(|_| PitchShifter::new(sampling_rate, interpolation)),
+            line_depths: Default::default(),
+            line_depth_rate: rate_from_time(ALL_LINES_RAMP_SECONDS, sampling_rate),
             shelf_g: calc_g(f64::from((SHELF_FREQ / sampling_rate).min(0.45))),
             shelf_two_r: calc_two_r(SHELF_Q),
         }
     }
 
-    /// Here "damping" is the sqrt of the damping gain.
+    /// `freeze` crossfades from the damped loop to an undamped loop with unity feedback,
+    /// so at 1.0 the loop holds its energy forever.
     ///
-    /// So, lower values of damping mean more damping!
+    /// `size` scales the length of every delay line, and must be no larger than the
+    /// `max_size` the loop was created with.
     ///
-    /// `modulation_depth` is in samples and controls depth of modulation. 4ms is fine.
-    /// `modulation_rate` is in cycles per sample and controls speed of modulation. 6 hz is fine.
-    #[allow(clippy::cast_possible_truncation)]
+    /// The shimmer is part of the damped loop, so it fades out as `freeze` engages.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::too_many_arguments
+    )]
     pub fn process(
         &mut self,
         mut input: [f32; CHANNELS],
         feedback: f32,
-        damping: f32,
-        modulation_depth: f32,
-        modulation_rate: f32,
+        damping: &Damping,
+        modulation: &Modulation,
+        shimmer: Shimmer,
+        freeze: f32,
+        size: f32,
     ) -> [f32; CHANNELS] {
-        let delayed = {
-            let delayed = self.delay.read();
-            let modulated_delay = self.modulated_delay.read(modulation_depth, modulation_rate);
-
-            let mut output = [0f32; CHANNELS];
-            let (a, b) = output.split_at_mut(delayed.len());
-            a.copy_from_slice(&delayed);
-            b.copy_from_slice(&[modulated_delay]);
+        // The low shelf makes up the difference between the loop gain we'd need for
+        // low frequencies to decay `bass_multiplier` times as slowly, and the actual
+        // loop gain.
+        let bass_sqrt_gain = f64::from(
+            feedback
+                .max(MIN_BASS_FEEDBACK)
+                .powf(1.0 / damping.bass_multiplier - 1.0)
+                .sqrt(),
+        );
+        let bass_g = calc_g(f64::from(damping.bass_crossover.min(0.45)));
+        let feedback = lerp(feedback, 1.0, freeze);
+        let mut delayed = [0f32; CHANNELS];
+        for (i, (((delay, length), line_depth), output)) in self
+            .delays
+            .iter_mut()
+            .zip(self.delay_lengths)
+            .zip(self.line_depths.iter_mut())
+            .zip(delayed.iter_mut())
+            .enumerate()
+        {
+            let length = scale_delay(length, size).min(delay.max_delay() as f32);
+            // Fade modulation in and out of lines rather than switching it, to avoid clicks
+            // when `all_lines` changes.
+            let line_depth = line_depth.process(
+                if modulation.all_lines || i == MODULATED_CHANNEL {
+                    1.0
+                } else {
+                    0.0
+                },
+                self.line_depth_rate,
+            );
+            // Keep the modulation from eating up the whole line at small sizes.
+            let depth = (modulation.depth * line_depth).min(length * 0.5);
+            let thawed = delay.read(length, depth, modulation.rate);
 
-            output
-        };
+            // Interpolating between samples loses a bit of energy on every trip around
+            // the loop, so while frozen we read at a whole-sample delay without modulation.
+            *output = lerp(thawed, delay.read_unmodulated(length.round()), freeze);
+        }
 
         let mut filtered = delayed;
-        for (filter, channel) in self.shelf.iter_mut().zip(filtered.iter_mut()) {
-            // We apply damping only to the last unmodulated channel
-            *channel = filter
+        for (((filter, low_filter), shifter), channel) in self
+            .shelf
+            .iter_mut()
+            .zip(self.low_shelf.iter_mut())
+            .zip(self.shifters.iter_mut())
+            .zip(filtered.iter_mut())
+        {
+            let damped = filter
                 .process_high_shelf(std::iter::once(GainInput {
                     x: f64::from(*channel),
                     params: GainRawParams {
                         g: self.shelf_g,
                         two_r: self.shelf_two_r,
-                        sqrt_gain: f64::from(damping),
+                        sqrt_gain: f64::from(damping.high),
+                    },
+                }))
+                .next()
+                .unwrap();
+            let damped = low_filter
+                .process_low_shelf(std::iter::once(GainInput {
+                    x: damped,
+                    params: GainRawParams {
+                        g: bass_g,
+                        two_r: self.shelf_two_r,
+                        sqrt_gain: bass_sqrt_gain,
                     },
                 }))
                 .next()
                 .unwrap() as f32;
+            // The shifter has unity gain, so mixing it in can't make the loop unstable.
+            let shimmered = if shimmer.amount > 0.0 {
+                lerp(damped, shifter.read(shimmer.ratio), shimmer.amount)
+            } else {
+                damped
+            };
+            shifter.write(damped);
+            *channel = lerp(shimmered, *channel, freeze);
         }
 
         // We use a householder matrix to mix the channels of the delayed output into the input
@@ -84,18 +224,36 @@ impl MultiChannelFeedbackLoop {
                     * delayed;
             }
         }
-        self.delay
-            .write(input[..UNMODULATED_CHANNELS].try_into().unwrap());
-        self.modulated_delay.write(input[UNMODULATED_CHANNELS]);
+        for (delay, input) in self.delays.iter_mut().zip(input) {
+            delay.write(input);
+        }
         delayed
     }
 
+    /// The average length of the delay lines at `size`, in samples.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn average_delay(&self, size: f32) -> f32 {
+        self.delays
+            .iter()
+            .zip(self.delay_lengths)
+            .map(|(delay, length)| scale_delay(length, size).min(delay.max_delay() as f32))
+            .sum::<f32>()
+            / CHANNELS as f32
+    }
+
     pub fn reset(&mut self) {
-        self.delay.reset();
-        self.modulated_delay.reset();
-        for shelf in &mut self.shelf {
+        for delay in &mut self.delays {
+            delay.reset();
+        }
+        for shelf in self.shelf.iter_mut().chain(self.low_shelf.iter_mut()) {
             shelf.reset();
         }
+        for shifter in &mut self.shifters {
+            shifter.reset();
+        }
+        for line_depth in &mut self.line_depths {
+            line_depth.reset();
+        }
     }
 }
 
@@ -105,12 +263,24 @@ mod tests {
 
     use super::*;
     use crate::diffuser::CHANNELS;
+    use dsp::test_utils::windowed_rfft;
+    use more_asserts::{assert_gt, assert_lt};
     use snapshots::assert_snapshot;
 
-    fn impulse_response_for_damping(name: &str, damping: f32, depth: f32, rate: f32) {
-        const SNAPSHOT_LENGTH: usize = 48_000 * 2;
-        const FEEDBACK: f32 = 0.85;
-        const SAMPLING_RATE: f32 = 48000.0;
+    const SAMPLING_RATE: f32 = 48000.0;
+
+    const NO_SHIMMER: Shimmer = Shimmer {
+        amount: 0.0,
+        ratio: 1.0,
+    };
+
+    const NO_MODULATION: Modulation = Modulation {
+        depth: 0.0,
+        rate: 0.0,
+        all_lines: false,
+    };
+
+    fn make_feedback_loop() -> MultiChannelFeedbackLoop {
         const DELAYS_MS: [f32; CHANNELS] = [
             110.147_446,
             113.727_97,
@@ -121,45 +291,265 @@ mod tests {
             177.914_43,
             193.846_86,
         ];
-        let mut feedback_loop = MultiChannelFeedbackLoop::new(
+        MultiChannelFeedbackLoop::new(
             DELAYS_MS.map(|d| (d / 1000.0 * SAMPLING_RATE).round() as usize),
             SAMPLING_RATE,
-        );
+            Interpolation::Linear,
+            1.0,
+        )
+    }
+
+    fn render_impulse_response(
+        damping: &Damping,
+        modulation: &Modulation,
+        shimmer: Shimmer,
+    ) -> Vec<f32> {
+        const SNAPSHOT_LENGTH: usize = 48_000 * 2;
+        const FEEDBACK: f32 = 0.85;
+        let mut feedback_loop = make_feedback_loop();
+        let mut process = |input| {
+            feedback_loop.process(input, FEEDBACK, damping, modulation, shimmer, 0.0, 1.0)[0]
+        };
         let mut output = vec![0.0; SNAPSHOT_LENGTH];
-        output[0] = feedback_loop.process(
-            [1.0; CHANNELS],
-            FEEDBACK,
-            damping,
-            depth * SAMPLING_RATE,
-            rate / SAMPLING_RATE,
-        )[0];
+        output[0] = process([1.0; CHANNELS]);
         for output in output.iter_mut().skip(1) {
-            *output = feedback_loop.process(
-                [0.0; CHANNELS],
-                FEEDBACK,
-                damping,
-                depth * SAMPLING_RATE,
-                rate / SAMPLING_RATE,
-            )[0];
+            *output = process([0.0; CHANNELS]);
+        }
+        output
+    }
+
+    fn damping_with_bass(high: f32, bass_multiplier: f32) -> Damping {
+        Damping {
+            high,
+            bass_multiplier,
+            bass_crossover: 250.0 / SAMPLING_RATE,
         }
+    }
+
+    fn impulse_response_for_damping(
+        name: &str,
+        damping: f32,
+        depth: f32,
+        rate: f32,
+        all_lines: bool,
+    ) {
+        let output = render_impulse_response(
+            &damping_with_bass(damping, 1.0),
+            &Modulation {
+                depth: depth * SAMPLING_RATE,
+                rate: rate / SAMPLING_RATE,
+                all_lines,
+            },
+            NO_SHIMMER,
+        );
         assert_snapshot!(&format!("feedback/{name}"), 48000, output);
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
     fn impulse_response() {
-        impulse_response_for_damping("impulse_response", 1.0, 0.0, 0.0);
+        impulse_response_for_damping("impulse_response", 1.0, 0.0, 0.0, false);
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
     fn impulse_response_damped() {
-        impulse_response_for_damping("impulse_response_damped", 0.5, 0.0, 0.0);
+        impulse_response_for_damping("impulse_response_damped", 0.5, 0.0, 0.0, false);
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
     fn impulse_response_modulated() {
-        impulse_response_for_damping("impulse_response_modulated", 1.0, 0.004, 6.0);
+        impulse_response_for_damping("impulse_response_modulated", 1.0, 0.004, 6.0, false);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_response_modulated_all_lines() {
+        impulse_response_for_damping(
+            "impulse_response_modulated_all_lines",
+            1.0,
+            0.004,
+            6.0,
+            true,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_response_bass_boosted() {
+        assert_snapshot!(
+            "feedback/impulse_response_bass_boosted",
+            48000,
+            render_impulse_response(&damping_with_bass(1.0, 2.0), &NO_MODULATION, NO_SHIMMER,)
+        );
+    }
+
+    /// Returns the ratio of energy below and above the crossover late in the tail.
+    #[allow(clippy::cast_precision_loss)]
+    fn late_bass_ratio(bass_multiplier: f32) -> f32 {
+        const WINDOW: usize = 16384;
+        let output = render_impulse_response(
+            &damping_with_bass(1.0, bass_multiplier),
+            &NO_MODULATION,
+            NO_SHIMMER,
+        );
+        let mut tail = output[output.len() - WINDOW..].to_vec();
+        let spectrum = windowed_rfft(&mut tail);
+        let bin_hz = SAMPLING_RATE / WINDOW as f32;
+        let energy = |range: std::ops::Range<f32>| {
+            spectrum
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| range.contains(&(*i as f32 * bin_hz)))
+                .map(|(_, x)| x.norm_sqr())
+                .sum::<f32>()
+        };
+        energy(20.0..125.0) / energy(2000.0..8000.0)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn bass_multiplier_changes_low_decay() {
+        let neutral = late_bass_ratio(1.0);
+        assert_gt!(late_bass_ratio(2.0), neutral * 10.0);
+        assert_lt!(late_bass_ratio(0.5), neutral * 0.1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn impulse_response_shimmer() {
+        assert_snapshot!(
+            "feedback/impulse_response_shimmer",
+            48000,
+            render_impulse_response(
+                &damping_with_bass(1.0, 1.0),
+                &NO_MODULATION,
+                Shimmer {
+                    amount: 0.5,
+                    ratio: 2.0,
+                },
+            )
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn full_shimmer_is_stable() {
+        const LENGTH: usize = 48_000 * 10;
+        const WINDOW: usize = 4800;
+        for ratio in [2.0, 1.5, 0.5] {
+            let mut feedback_loop = make_feedback_loop();
+            let damping = damping_with_bass(1.0, 2.0);
+            let shimmer = Shimmer { amount: 1.0, ratio };
+            let noise = dsp::test_utils::white_noise(WINDOW);
+            let output = noise
+                .into_iter()
+                .chain(std::iter::repeat(0.0))
+                .take(LENGTH)
+                .map(|x| {
+                    feedback_loop.process(
+                        [x; CHANNELS],
+                        0.97,
+                        &damping,
+                        &NO_MODULATION,
+                        shimmer,
+                        0.0,
+                        1.0,
+                    )[0]
+                })
+                .collect::<Vec<_>>();
+            assert!(output.iter().all(|x| x.is_finite()));
+            let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+            let start = energy(&output[WINDOW..2 * WINDOW]);
+            let end = energy(&output[LENGTH - WINDOW..]);
+            assert_lt!(end, start * 0.01, "ratio {ratio}");
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn switching_all_lines_does_not_click() {
+        const LENGTH: usize = 48_000;
+        let mut feedback_loop = make_feedback_loop();
+        let damping = damping_with_bass(1.0, 1.0);
+        let modulation = |all_lines| Modulation {
+            depth: 0.004 * SAMPLING_RATE,
+            rate: 6.0 / SAMPLING_RATE,
+            all_lines,
+        };
+        // With no feedback, the first line just delays this smooth input.
+        let input = dsp::test_utils::sine(LENGTH, 100.0 / SAMPLING_RATE);
+        let output = input
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                feedback_loop.process(
+                    [*x; CHANNELS],
+                    0.0,
+                    &damping,
+                    &modulation(i >= LENGTH / 2),
+                    NO_SHIMMER,
+                    0.0,
+                    1.0,
+                )[0]
+            })
+            .collect::<Vec<_>>();
+        let max_step = output
+            .windows(2)
+            .map(|x| (x[1] - x[0]).abs())
+            .fold(0.0, f32::max);
+        assert_lt!(max_step, 0.05);
+    }
+
+    fn correlation(a: &[f32], b: &[f32]) -> f32 {
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[allow(clippy::cast_precision_loss)]
+    fn switching_all_lines_on_keeps_lines_decorrelated() {
+        const SWITCH: usize = 24_000;
+        const SETTLED: usize = SWITCH + 4800;
+        const CYCLE: usize = 4800;
+        let mut feedback_loop = make_feedback_loop();
+        let damping = damping_with_bass(1.0, 1.0);
+        let modulation = |all_lines| Modulation {
+            depth: 0.004 * SAMPLING_RATE,
+            rate: 1.0 / CYCLE as f32,
+            all_lines,
+        };
+        // With no feedback and a ramp input, each line outputs the ramp delayed by the
+        // current length of the line, so subtracting the ramp leaves just the LFO.
+        let mut lfos = vec![Vec::new(); CHANNELS];
+        for i in 0..SETTLED + CYCLE {
+            let output = feedback_loop.process(
+                [i as f32 * 1e-3; CHANNELS],
+                0.0,
+                &damping,
+                &modulation(i >= SWITCH),
+                NO_SHIMMER,
+                0.0,
+                1.0,
+            );
+            if i >= SETTLED {
+                for (lfo, output) in lfos.iter_mut().zip(output) {
+                    lfo.push(output - i as f32 * 1e-3);
+                }
+            }
+        }
+        for lfo in &mut lfos {
+            let mean = lfo.iter().sum::<f32>() / lfo.len() as f32;
+            for x in lfo.iter_mut() {
+                *x -= mean;
+            }
+        }
+        for a in 0..CHANNELS {
+            for b in (a + 1)..CHANNELS {
+                assert_lt!(correlation(&lfos[a], &lfos[b]), 0.8);
+            }
+        }
     }
 }
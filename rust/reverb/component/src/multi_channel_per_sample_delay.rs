@@ -17,10 +17,12 @@ impl<const CHANNELS: usize> MultiChannelPerSampleDelay<CHANNELS> {
         }
     }
 
-    pub fn read(&self) -> [f32; CHANNELS] {
+    /// Reads each channel at the given delay, which must be no longer than that
+    /// channel's line. Fractional delays interpolate linearly between samples.
+    pub fn read_delayed(&self, delays: &[f32; CHANNELS]) -> [f32; CHANNELS] {
         let mut output = [0.0; CHANNELS];
-        for (delay, output) in self.delays.iter().zip(output.iter_mut()) {
-            *output = delay.read();
+        for ((delay, output), samples) in self.delays.iter().zip(output.iter_mut()).zip(delays) {
+            *output = delay.read_interpolated(*samples);
         }
         output
     }
@@ -49,10 +51,11 @@ mod tests {
     fn process(
         input: impl IntoIterator<Item = [f32; CHANNELS]>,
         delay: &mut MultiChannelPerSampleDelay<CHANNELS>,
+        delays: [f32; CHANNELS],
     ) -> Vec<[f32; CHANNELS]> {
         let mut output = Vec::new();
         for samples in input {
-            let x = delay.read();
+            let x = delay.read_delayed(&delays);
             delay.write(&samples);
             output.push(x);
         }
@@ -63,8 +66,9 @@ mod tests {
         input: impl IntoIterator<Item = [f32; CHANNELS]>,
         expected: impl IntoIterator<Item = [f32; CHANNELS]>,
         delay: &mut MultiChannelPerSampleDelay<CHANNELS>,
+        delays: [f32; CHANNELS],
     ) {
-        for (actual, expected) in process(input, delay).into_iter().zip(expected) {
+        for (actual, expected) in process(input, delay, delays).into_iter().zip(expected) {
             for (a, e) in actual.iter().zip(expected) {
                 assert_approx_eq!(a, e, 1e-6);
             }
@@ -78,11 +82,24 @@ mod tests {
             [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]],
             [[0.0, 0.0], [0.0, 0.0], [0.0, 2.0]],
             &mut delay,
+            [4.0, 2.0],
         );
         check_process(
             [[7.0, 8.0], [9.0, 10.0], [11.0, 12.0]],
             [[0.0, 4.0], [1.0, 6.0], [3.0, 8.0]],
             &mut delay,
+            [4.0, 2.0],
+        );
+    }
+
+    #[test]
+    fn read_shorter_than_buffer() {
+        let mut delay = MultiChannelPerSampleDelay::new([4, 2]);
+        check_process(
+            [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]],
+            [[0.0, 0.0], [0.0, 2.0], [1.0, 4.0]],
+            &mut delay,
+            [2.0, 1.0],
         );
     }
 }
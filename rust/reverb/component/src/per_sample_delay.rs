@@ -14,14 +14,29 @@ impl PerSampleDelay {
         }
     }
 
-    pub fn read(&self) -> f32 {
-        self.buffer[self.head]
-    }
-
     pub fn read_with_offset(&self, offset: usize) -> f32 {
         self.buffer[(self.head + offset) % self.buffer.len()]
     }
 
+    /// Reads the sample written `delay` samples ago. `delay` must be between 1 and
+    /// the length of the line.
+    pub fn read_delayed(&self, delay: usize) -> f32 {
+        self.read_with_offset(self.buffer.len() - delay)
+    }
+
+    /// Reads `delay` samples ago, linearly interpolating between samples. `delay` must
+    /// be between 1 and the length of the line.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn read_interpolated(&self, delay: f32) -> f32 {
+        let floor = delay.floor() as usize;
+        let a = self.read_delayed(floor);
+        if floor == self.get_delay() {
+            return a;
+        }
+        let b = self.read_delayed(floor + 1);
+        a + (b - a) * delay.fract()
+    }
+
     pub fn write(&mut self, input: f32) {
         self.buffer[self.head] = input;
         self.head = (self.head + 1) % self.buffer.len();
@@ -47,7 +62,7 @@ mod tests {
         input
             .into_iter()
             .map(|sample| {
-                let output = delay.read();
+                let output = delay.read_delayed(delay.get_delay());
                 delay.write(sample);
                 output
             })
@@ -89,6 +104,20 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn read_delayed_shorter_than_line() {
+        let mut delay = PerSampleDelay::new(5);
+        let output: Vec<_> = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .into_iter()
+            .map(|sample| {
+                let output = delay.read_delayed(2);
+                delay.write(sample);
+                output
+            })
+            .collect();
+        assert!(all_approx_eq(output, [0., 0., 1., 2., 3., 4.], 1e-6));
+    }
+
     #[test]
     fn reset() {
         let mut delay = PerSampleDelay::new(3);
@@ -2,34 +2,103 @@ use crate::per_sample_delay::PerSampleDelay;
 
 mod lfo;
 
+/// How to read between samples at fractional delays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// Linear interpolation between the two nearest samples.
+    Linear,
+
+    /// Four-point cubic hermite interpolation. This has a flatter high-frequency
+    /// response than linear interpolation, at a higher CPU cost.
+    Cubic,
+}
+
 /// A modulated delay line optimized to read and write one sample at a time.
 #[derive(Debug, Clone)]
 pub struct PerSampleModulatedDelay {
     delay: PerSampleDelay,
     lfo: lfo::Lfo,
+    interpolation: Interpolation,
 }
 
 impl PerSampleModulatedDelay {
+    /// Here `phase` is the starting phase of the LFO, from 0 to 1.
     #[allow(clippy::cast_precision_loss)]
-    pub fn new(delay: usize) -> Self {
+    pub fn new(delay: usize, interpolation: Interpolation, phase: f32) -> Self {
         Self {
             delay: PerSampleDelay::new(delay),
-            lfo: lfo::Lfo::new(),
+            lfo: lfo::Lfo::new(phase),
+            interpolation,
         }
     }
 
-    /// Here lfo depth is in delay samples, and rate is LFO cycles per samples.
+    /// Here `delay` is the unmodulated delay in samples, which must be no longer than
+    /// the line, lfo depth is in delay samples, and rate is LFO cycles per samples.
+    ///
+    /// The LFO only ever shortens the delay. It keeps running at zero `depth`, so
+    /// fading the depth back in picks up at the same point in its cycle.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn read(&mut self, delay: f32, depth: f32, rate: f32) -> f32 {
+        let modulation = self.lfo.run(depth, rate);
+        if depth <= 0.0 {
+            return self.read_unmodulated(delay);
+        }
+        let whole = delay.ceil();
+        self.read_shortened(whole as usize, modulation + (whole - delay))
+    }
+
+    /// Reads at `offset` samples shorter than `delay`, interpolating between samples.
+    ///
+    /// `offset` must be non-negative and less than `delay`.
     #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-    pub fn read(&mut self, depth: f32, rate: f32) -> f32 {
-        let offset = self.lfo.run(depth, rate);
+    pub fn read_shortened(&self, delay: usize, offset: f32) -> f32 {
+        // We keep the whole-sample part of the read position separate from the
+        // fractional offset, so changing `delay` doesn't affect interpolation precision.
+        let base = self.delay.get_delay() - delay;
+
+        match self.interpolation {
+            Interpolation::Linear => {
+                let floor = (offset.floor() as usize).min(delay - 2);
+                let a = self.delay.read_with_offset(base + floor);
+                let b = self.delay.read_with_offset(base + floor + 1);
 
-        // We use linear interpolation to get the fractional delay sample.
-        let floor = (offset.floor() as usize).min(self.delay.get_delay() - 2);
-        let a = self.delay.read_with_offset(floor);
-        let b = self.delay.read_with_offset(floor + 1);
+                let t = offset - floor as f32;
+                a + (b - a) * t
+            }
+            Interpolation::Cubic => {
+                // We need a sample on either side of the interpolated segment, so near the
+                // ends of the buffer we extrapolate from the closest full segment.
+                let floor = (offset.floor() as usize).clamp(1, delay - 3);
+                let y0 = self.delay.read_with_offset(base + floor - 1);
+                let y1 = self.delay.read_with_offset(base + floor);
+                let y2 = self.delay.read_with_offset(base + floor + 1);
+                let y3 = self.delay.read_with_offset(base + floor + 2);
 
-        let t = offset - floor as f32;
-        a + (b - a) * t
+                let t = offset - floor as f32;
+                let c1 = 0.5 * (y2 - y0);
+                let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+                ((c3 * t + c2) * t + c1) * t + y1
+            }
+        }
+    }
+
+    /// Reads at `delay` samples, ignoring the LFO. This only interpolates when `delay`
+    /// falls between samples, so at whole-sample delays it passes the signal through
+    /// without any loss.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn read_unmodulated(&self, delay: f32) -> f32 {
+        let whole = delay.ceil();
+        if delay.fract() == 0.0 {
+            self.delay.read_delayed(whole as usize)
+        } else {
+            self.read_shortened(whole as usize, whole - delay)
+        }
+    }
+
+    /// The longest delay this line can be read at, in samples.
+    pub fn max_delay(&self) -> usize {
+        self.delay.get_delay()
     }
 
     pub fn write(&mut self, input: f32) {
@@ -44,12 +113,14 @@ impl PerSampleModulatedDelay {
 
 #[cfg(test)]
 mod tests {
+    use conformal_component::audio::all_approx_eq;
+    use more_asserts::assert_lt;
     use snapshots::assert_snapshot;
 
     use super::*;
 
     #[test]
-    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
     #[cfg_attr(miri, ignore)]
     fn lofi_chorus() {
         const SAMPLING_RATE: f32 = 48000.0;
@@ -58,12 +129,79 @@ mod tests {
         const RATE: f32 = 6.0 / SAMPLING_RATE;
 
         let test_sig = dsp::test_utils::sine(SAMPLING_RATE as usize, 1123. / SAMPLING_RATE);
-        let mut modulated_delay = PerSampleModulatedDelay::new(MAX_DELAY);
+        let mut modulated_delay =
+            PerSampleModulatedDelay::new(MAX_DELAY, Interpolation::Linear, 0.0);
         let output = test_sig.iter().map(|x| {
-            let y = modulated_delay.read(DEPTH, RATE);
+            let y = modulated_delay.read(MAX_DELAY as f32, DEPTH, RATE);
             modulated_delay.write(*x * 0.25);
             y
         });
         assert_snapshot!("modulated_delay/lofi_chorus", 48000, output);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[allow(clippy::cast_precision_loss)]
+    fn shorter_delay_matches_shorter_line() {
+        const DELAY: usize = 64;
+        const DEPTH: f32 = 20.0;
+        const RATE: f32 = 20.0 / 48000.0;
+
+        let test_sig = dsp::test_utils::white_noise(1024);
+        let process = |mut modulated_delay: PerSampleModulatedDelay| {
+            test_sig
+                .iter()
+                .map(|x| {
+                    let y = modulated_delay.read(DELAY as f32, DEPTH, RATE);
+                    modulated_delay.write(*x);
+                    y
+                })
+                .collect::<Vec<_>>()
+        };
+        let long = process(PerSampleModulatedDelay::new(
+            DELAY * 2,
+            Interpolation::Cubic,
+            0.0,
+        ));
+        let short = process(PerSampleModulatedDelay::new(
+            DELAY,
+            Interpolation::Cubic,
+            0.0,
+        ));
+        assert!(all_approx_eq(long, short, 1e-6));
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn interpolation_error_db(interpolation: Interpolation) -> f32 {
+        const DELAY: usize = 64;
+        const DEPTH: f32 = 20.0;
+        const RATE: f32 = 20.0 / 48000.0;
+        let incr = 3000.0 / 48000.0;
+
+        let test_sig = dsp::test_utils::sine(8192, incr);
+        let mut modulated_delay = PerSampleModulatedDelay::new(DELAY, interpolation, 0.0);
+        let mut lfo = lfo::Lfo::new(0.0);
+        let (error, signal) = test_sig
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let y = modulated_delay.read(DELAY as f32, DEPTH, RATE);
+                modulated_delay.write(*x);
+                let position = i as f32 - DELAY as f32 + lfo.run(DEPTH, RATE);
+                (y, (std::f32::consts::TAU * incr * position).sin())
+            })
+            .skip(DELAY)
+            .fold((0.0, 0.0), |(error, signal), (y, expected)| {
+                (error + (y - expected).powi(2), signal + expected.powi(2))
+            });
+        10.0 * (error / signal).log10()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn cubic_interpolation_reduces_error() {
+        let linear = interpolation_error_db(Interpolation::Linear);
+        let cubic = interpolation_error_db(Interpolation::Cubic);
+        assert_lt!(cubic, linear - 6.0);
+    }
 }
@@ -1,60 +1,42 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum LfoDirection {
-    Up,
-    Down,
-}
-
 /// Triangle LFO for controlling the modulated delay line.
+///
+/// We keep the phase separately from the depth, so changing the depth scales the
+/// output without moving the LFO along its cycle.
 #[derive(Debug, Clone)]
 pub struct Lfo {
-    value: f32,
-    direction: LfoDirection,
+    /// Current phase, from 0 to 1.
+    phase: f32,
+
+    /// Phase (from 0 to 1) to start at.
+    initial_phase: f32,
 }
 
 impl Lfo {
-    pub fn new() -> Self {
+    /// Create an LFO that starts at `phase`, which goes from 0 to 1.
+    pub fn new(phase: f32) -> Self {
         Self {
-            value: 0f32,
-            direction: LfoDirection::Up,
+            phase,
+            initial_phase: phase,
         }
     }
 
     pub fn reset(&mut self) {
-        self.value = 0.0;
-        self.direction = LfoDirection::Up;
+        self.phase = self.initial_phase;
     }
 
-    /// Run the LFO.
+    /// Run the LFO, returning a value between 0 and `depth`.
     ///
     /// Here `rate` is the rate in lfo cycles/samples
     pub fn run(&mut self, depth: f32, rate: f32) -> f32 {
-        if self.value > depth {
-            self.direction = LfoDirection::Down;
-        }
+        let out = if self.phase < 0.5 {
+            depth * 2.0 * self.phase
+        } else {
+            depth * 2.0 * (1.0 - self.phase)
+        };
 
-        let max = depth.max(self.value);
-        let incr = if rate > 0.5 { 0.0 } else { max * 2.0 * rate };
-        let out = self.value;
-        match self.direction {
-            LfoDirection::Down => {
-                self.value -= incr;
-                if self.value <= 0f32 {
-                    self.value = (-self.value).min(depth);
-                    self.direction = LfoDirection::Up;
-                }
-            }
-            LfoDirection::Up => {
-                self.value += incr;
-                if self.value >= max {
-                    self.value = max + (max - self.value);
-                    self.direction = LfoDirection::Down;
-                }
-            }
-        }
-
-        // Flush small values to zero to prvent denormals.
-        if self.value < 1e-15 {
-            self.value = 0.0;
+        // Above nyquist we'd alias, so we hold the LFO still instead.
+        if rate <= 0.5 {
+            self.phase = (self.phase + rate).fract();
         }
         out
     }
@@ -67,7 +49,7 @@ mod tests {
 
     #[test]
     fn basics() {
-        let mut lfo = Lfo::new();
+        let mut lfo = Lfo::new(0.0);
         let values = std::iter::repeat_with(move || lfo.run(2.0, 0.25))
             .take(8)
             .collect::<Vec<_>>();
@@ -80,7 +62,7 @@ mod tests {
 
     #[test]
     fn avoids_aliasing_by_using_constant_lfo() {
-        let mut lfo = Lfo::new();
+        let mut lfo = Lfo::new(0.0);
         let values = std::iter::repeat_with(move || lfo.run(2.0, 0.6))
             .take(4)
             .collect::<Vec<_>>();
@@ -90,7 +72,7 @@ mod tests {
 
     #[test]
     fn okay_to_have_zero_depth_equal() {
-        let mut lfo = Lfo::new();
+        let mut lfo = Lfo::new(0.0);
         let values = std::iter::repeat_with(move || lfo.run(0.0, 0.25))
             .take(4)
             .collect::<Vec<_>>();
@@ -98,13 +80,22 @@ mod tests {
     }
 
     #[test]
-    fn jump_to_zero_depth() {
-        let mut lfo = Lfo::new();
-        lfo.run(100.0, 0.25);
-        lfo.run(100.0, 0.25);
-        assert!(approx_eq(lfo.run(0.0, 0.25), 100.0, 1e-6));
-        let x = lfo.run(0.0, 0.25);
-        assert!(x < 100.0);
-        assert!(lfo.run(0.0, 0.25) < x);
+    fn depth_changes_keep_phase() {
+        let mut lfo = Lfo::new(0.0);
+        lfo.run(100.0, 0.125);
+        lfo.run(100.0, 0.125);
+        let values = std::iter::repeat_with(move || lfo.run(2.0, 0.125))
+            .take(4)
+            .collect::<Vec<_>>();
+        assert!(all_approx_eq(values, vec![1.0, 1.5, 2.0, 1.5], 1e-6));
+    }
+
+    #[test]
+    fn starts_at_phase() {
+        let mut lfo = Lfo::new(0.75);
+        let values = std::iter::repeat_with(move || lfo.run(2.0, 0.25))
+            .take(4)
+            .collect::<Vec<_>>();
+        assert!(all_approx_eq(values, vec![1.0, 0.0, 1.0, 2.0], 1e-6));
     }
 }
@@ -0,0 +1,152 @@
+//! A delay-line pitch shifter, used for shimmer in the feedback loop.
+//!
+//! Two taps sweep across a short window of the delay line at a rate set by the pitch
+//! ratio, and we crossfade between them so neither tap is heard as it jumps back to
+//! the other end of the window.
+
+use num_derive::FromPrimitive;
+
+use crate::per_sample_modulated_delay::{Interpolation, PerSampleModulatedDelay};
+
+/// Length of the window the taps sweep across, in seconds.
+const WINDOW_SECONDS: f32 = 0.05;
+
+/// Extra samples at the short end of the window, so interpolation never reads
+/// past the newest sample.
+const MARGIN: usize = 4;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interval {
+    #[default]
+    OctaveUp,
+    FifthUp,
+    OctaveDown,
+}
+
+impl Interval {
+    /// The ratio of the shifted frequency to the original.
+    pub fn ratio(self) -> f32 {
+        match self {
+            Interval::OctaveUp => 2.0,
+            // An equal-tempered fifth, to match the other pitched instruments in a mix.
+            Interval::FifthUp => 2.0f32.powf(7.0 / 12.0),
+            Interval::OctaveDown => 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PitchShifter {
+    delay: PerSampleModulatedDelay,
+    window: f32,
+
+    /// Position of the first tap in the window, from 0 to 1. The second tap is
+    /// half a window away.
+    phase: f32,
+}
+
+impl PitchShifter {
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(sampling_rate: f32, interpolation: Interpolation) -> Self {
+        let window = (WINDOW_SECONDS * sampling_rate).round() as usize;
+        Self {
+            delay: PerSampleModulatedDelay::new(window + 2 * MARGIN, interpolation, 0.0),
+            window: window as f32,
+            phase: 0.0,
+        }
+    }
+
+    /// Reads the pitch shifted signal, where `ratio` is the ratio of output to input
+    /// frequency.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn read(&mut self, ratio: f32) -> f32 {
+        let length = self.delay.max_delay();
+        let tap = |phase: f32| {
+            // Windowing by sin^2 means the two taps' gains always sum to 1, and each
+            // tap is silent as it wraps around.
+            let gain = (std::f32::consts::PI * phase).sin().powi(2);
+            let delay = (1.0 - phase) * self.window + MARGIN as f32;
+            gain * self.delay.read_shortened(length, length as f32 - delay)
+        };
+        let output = tap(self.phase) + tap((self.phase + 0.5).fract());
+
+        // To raise the pitch, the delay must get shorter by `ratio - 1` samples every sample.
+        self.phase = (self.phase + (ratio - 1.0) / self.window).rem_euclid(1.0);
+        output
+    }
+
+    pub fn write(&mut self, input: f32) {
+        self.delay.write(input);
+    }
+
+    pub fn reset(&mut self) {
+        self.delay.reset();
+        self.phase = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn shifted_tuning(ratio: f32) -> f32 {
+        const SAMPLING_RATE: f32 = 48000.0;
+        const FREQ: f32 = 440.0;
+        let mut shifter = PitchShifter::new(SAMPLING_RATE, Interpolation::Cubic);
+        let mut output = dsp::test_utils::sine(48000, FREQ / SAMPLING_RATE)
+            .into_iter()
+            .map(|x| {
+                let y = shifter.read(ratio);
+                shifter.write(x);
+                y
+            })
+            .skip(4800)
+            .collect::<Vec<_>>();
+        dsp::test_utils::estimate_tuning(&mut output) * SAMPLING_RATE / FREQ
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn octave_up() {
+        assert_approx_eq!(shifted_tuning(Interval::OctaveUp.ratio()), 2.0, 0.02);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn fifth_up() {
+        let ratio = Interval::FifthUp.ratio();
+        assert_approx_eq!(shifted_tuning(ratio), ratio, 0.02);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn octave_down() {
+        assert_approx_eq!(shifted_tuning(Interval::OctaveDown.ratio()), 0.5, 0.02);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn unity_ratio_is_a_delay() {
+        let mut shifter = PitchShifter::new(48000.0, Interpolation::Linear);
+        let input = dsp::test_utils::white_noise(4800);
+        let output = input
+            .iter()
+            .map(|x| {
+                let y = shifter.read(1.0);
+                shifter.write(*x);
+                y
+            })
+            .collect::<Vec<_>>();
+        // With the first tap at phase 0, we only hear the second tap, half a window back.
+        let delay = 1200 + MARGIN;
+        for (y, x) in output[delay..].iter().zip(&input) {
+            assert_approx_eq!(y, x, 1e-5);
+        }
+    }
+}
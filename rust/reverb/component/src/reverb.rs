@@ -1,179 +1,707 @@
-use conformal_component::ProcessingEnvironment;
 use conformal_component::audio::{Buffer, BufferMut};
-use dsp::iir::svf::{GainInput, GainRawParams, Svf, calc_g, calc_two_r};
+use conformal_component::{ProcessingEnvironment, ProcessingMode};
+use dsp::iir::svf::{GainInput, GainRawParams, Input, RawParams, Svf, calc_g, calc_two_r};
+use dsp::slew::{OnePoleSmoother, SlewLimiter, coeff_from_time, rate_from_time};
+use num_derive::FromPrimitive;
 use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
 
+use crate::convolver::Convolver;
 pub use crate::diffuser::CHANNELS;
 use crate::diffuser::{BLOCKS, Diffuser};
-use crate::multi_channel_feedback_loop::MultiChannelFeedbackLoop;
+use crate::ducker::{self, Ducker};
+use crate::gate::{self, Gate};
+use crate::multi_channel_feedback_loop::{Damping, Modulation, MultiChannelFeedbackLoop, Shimmer};
+use crate::per_sample_delay::PerSampleDelay;
+use crate::per_sample_modulated_delay::Interpolation;
+use crate::pitch_shifter;
+use crate::reverser::Reverser;
+
+/// The longest pre-delay we support, in seconds.
+pub const MAX_PRE_DELAY_SECONDS: f32 = 0.5;
+
+/// The longest gate hold we support, which is also the longest reverse segment, in seconds.
+pub const MAX_GATE_HOLD_SECONDS: f32 = 2.0;
+
+/// The smallest and largest room sizes we support, as multiples of the default size.
+pub const MIN_SIZE: f32 = 0.25;
+pub const MAX_SIZE: f32 = 2.0;
+
+/// The lowest low cut in Hz, where the wet low cut filter is effectively transparent.
+pub const LOW_CUT_OFF: f32 = 20.0;
+
+/// The highest high cut in Hz, where the wet high cut filter is effectively transparent.
+pub const HIGH_CUT_OFF: f32 = 20000.0;
+
+/// How long it takes the size to change by 1.0. Changing size sweeps every delay
+/// in the reverb, so we limit the rate to keep the resulting pitch glide gentle.
+const SIZE_SLEW_SECONDS: f32 = 0.5;
+
+/// How long it takes to fully engage or release freeze, in seconds.
+const FREEZE_RAMP_SECONDS: f32 = 0.05;
+
+/// Time constant for smoothing parameter changes, in seconds.
+const PARAM_SMOOTHING_SECONDS: f32 = 0.02;
+
+/// The most channels we support, for 5.1 surround.
+const MAX_CHANNELS: usize = 6;
+
+/// Index of the LFE channel in a 5.1 buffer.
+const LFE_CHANNEL: usize = 3;
+
+/// Block size of the convolution engine. Each sample costs this many multiplies for
+/// the first block of the impulse response, plus an FFT every block.
+const CONVOLUTION_BLOCK_SIZE: usize = 256;
+
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// The feedback delay network reverb.
+    #[default]
+    Algorithmic,
+
+    /// Convolves each channel with the loaded impulse response.
+    ///
+    /// The impulse response replaces the whole wet path, so the parameters that
+    /// shape the algorithmic reverb (including freeze) have no effect. The mix,
+    /// ducking and the gated and reverse modes still apply.
+    Convolution,
+}
 
 pub struct Reverb {
+    pre_delays: [PerSampleDelay; MAX_CHANNELS],
     diffuser: Diffuser,
     feedback_loop: MultiChannelFeedbackLoop,
-    shelves: [Svf; 2],
+
+    shelves: [Svf; MAX_CHANNELS],
     shelf_g: f64,
     shelf_two_r: f64,
-    modulation_depth: f32,
-    modulation_rate: f32,
+    low_cuts: [Svf; MAX_CHANNELS],
+    high_cuts: [Svf; MAX_CHANNELS],
+    cut_two_r: f64,
+    sampling_rate: f32,
+    freeze: SlewLimiter,
+    freeze_rate: f32,
+    size: SlewLimiter,
+    size_rate: f32,
+    ducker: Ducker,
+    gate: Gate,
+    reverser: Reverser<MAX_CHANNELS>,
+    convolvers: [Convolver; MAX_CHANNELS],
+    engine: Engine,
+    smoothers: Smoothers,
+    smoothing_coeff: f32,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Params {
     pub mix: f32,
-    pub feedback: f32,
+
+    /// Delay before the input reaches the diffuser, in seconds.
+    pub pre_delay: f32,
+
+    /// Time for the tail to decay by 60 dB, in seconds.
+    ///
+    /// This is exact for mid frequencies. `damping` makes high frequencies decay faster,
+    /// and `bass_multiplier` scales the decay time of low frequencies.
+    pub decay_time: f32,
     pub brightness: f32,
     pub damping: f32,
+
+    /// How much longer low frequencies take to decay than the rest of the tail.
+    pub bass_multiplier: f32,
+
+    /// Frequency below which `bass_multiplier` applies, in Hz.
+    pub bass_crossover: f32,
     pub early_reflections: f32,
     pub density: f32,
+
+    /// Cutoff of the high-pass filter on the wet signal, in Hz.
+    pub low_cut: f32,
+
+    /// Cutoff of the low-pass filter on the wet signal, in Hz.
+    /// This saturates just below nyquist at low sampling rates.
+    pub high_cut: f32,
+
+    /// Scales the length of every delay in the reverb, between `MIN_SIZE` and `MAX_SIZE`.
+    pub size: f32,
+
+    /// Stereo width of the wet signal, where 0 is mono, 1 is unchanged, and
+    /// higher values exaggerate the difference between channels.
+    /// Only applies to stereo buffers.
+    pub width: f32,
+
+    /// If set, the input channels are summed to mono before the reverb,
+    /// for mono sources on multichannel tracks. The dry signal is unaffected.
+    pub mono_input: bool,
+
+    /// Depth of the delay line modulation, in seconds.
+    pub modulation_depth: f32,
+
+    /// Rate of the delay line modulation, in Hz.
+    pub modulation_rate: f32,
+
+    /// If false, only one of the feedback delay lines is modulated.
+    pub modulate_all_lines: bool,
+
+    /// How much of the feedback is pitch shifted, from 0 to 1.
+    pub shimmer_amount: f32,
+
+    /// How far the shimmer shifts the feedback on each trip around the loop.
+    pub shimmer_interval: pitch_shifter::Interval,
+
+    /// How much the wet signal ducks while the dry input is active, from 0 to 1.
+    pub duck_amount: f32,
+
+    /// How quickly ducking responds to the dry input, in seconds.
+    pub duck_attack: f32,
+
+    /// How quickly the wet signal recovers once the dry input stops, in seconds.
+    pub duck_release: f32,
+
+    /// Whether the tail decays naturally, is gated, or is reversed.
+    pub mode: gate::Mode,
+
+    /// In gated mode, how long the tail is held after the input drops, in seconds.
+    /// In reverse mode, how long each reversed segment lasts, in seconds.
+    pub gate_hold: f32,
+
+    /// How long the gated tail or each reversed segment takes to close, in seconds.
+    pub gate_release: f32,
+
+    /// In gated mode, the input level above which the gate opens, as a linear gain.
+    pub gate_threshold: f32,
+
+    /// When set, the tail is held indefinitely and new input is ignored.
+    pub freeze: bool,
+
+    /// Whether the wet signal comes from the algorithmic reverb or from convolution
+    /// with the loaded impulse response.
+    pub engine: Engine,
+}
+
+/// Smooths each continuous parameter so that automation doesn't cause zipper noise.
+#[derive(Debug, Default)]
+struct Smoothers {
+    mix: OnePoleSmoother,
+    pre_delay: OnePoleSmoother,
+    decay_time: OnePoleSmoother,
+    brightness: OnePoleSmoother,
+    damping: OnePoleSmoother,
+    bass_multiplier: OnePoleSmoother,
+    bass_crossover: OnePoleSmoother,
+    early_reflections: OnePoleSmoother,
+    density: OnePoleSmoother,
+    low_cut: OnePoleSmoother,
+    high_cut: OnePoleSmoother,
+    width: OnePoleSmoother,
+    modulation_depth: OnePoleSmoother,
+    modulation_rate: OnePoleSmoother,
+    shimmer_amount: OnePoleSmoother,
+    duck_amount: OnePoleSmoother,
+}
+
+impl Smoothers {
+    fn process(&mut self, params: &Params, coeff: f32) -> Params {
+        Params {
+            mix: self.mix.process(params.mix, coeff),
+            pre_delay: self.pre_delay.process(params.pre_delay, coeff),
+            decay_time: self.decay_time.process(params.decay_time, coeff),
+            brightness: self.brightness.process(params.brightness, coeff),
+            damping: self.damping.process(params.damping, coeff),
+            bass_multiplier: self.bass_multiplier.process(params.bass_multiplier, coeff),
+            bass_crossover: self.bass_crossover.process(params.bass_crossover, coeff),
+            early_reflections: self
+                .early_reflections
+                .process(params.early_reflections, coeff),
+            density: self.density.process(params.density, coeff),
+            low_cut: self.low_cut.process(params.low_cut, coeff),
+            high_cut: self.high_cut.process(params.high_cut, coeff),
+            width: self.width.process(params.width, coeff),
+            mono_input: params.mono_input,
+            size: params.size,
+            modulation_depth: self
+                .modulation_depth
+                .process(params.modulation_depth, coeff),
+            modulation_rate: self.modulation_rate.process(params.modulation_rate, coeff),
+            modulate_all_lines: params.modulate_all_lines,
+            shimmer_amount: self.shimmer_amount.process(params.shimmer_amount, coeff),
+            shimmer_interval: params.shimmer_interval,
+            duck_amount: self.duck_amount.process(params.duck_amount, coeff),
+            duck_attack: params.duck_attack,
+            duck_release: params.duck_release,
+            mode: params.mode,
+            gate_hold: params.gate_hold,
+            gate_release: params.gate_release,
+            gate_threshold: params.gate_threshold,
+            freeze: params.freeze,
+            engine: params.engine,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.mix.reset();
+        self.pre_delay.reset();
+        self.decay_time.reset();
+        self.brightness.reset();
+        self.damping.reset();
+        self.bass_multiplier.reset();
+        self.bass_crossover.reset();
+        self.early_reflections.reset();
+        self.density.reset();
+        self.low_cut.reset();
+        self.high_cut.reset();
+        self.width.reset();
+        self.modulation_depth.reset();
+        self.modulation_rate.reset();
+        self.shimmer_amount.reset();
+        self.duck_amount.reset();
+    }
 }
 
 const SHELF_FREQ: f32 = 2000.0;
 const SHELF_Q: f64 = 0.707;
+const CUT_Q: f64 = 0.707;
+
+/// Delays `input` by `delay` samples, which must be no longer than the delay line.
+///
+/// Fractional delays are linearly interpolated, so smoothly changing the pre-delay
+/// doesn't step between whole samples.
+fn process_pre_delay(line: &mut PerSampleDelay, input: f32, delay: f32) -> f32 {
+    let output = if delay < 1.0 {
+        input + (line.read_delayed(1) - input) * delay
+    } else {
+        line.read_interpolated(delay)
+    };
+    line.write(input);
+    output
+}
+
+/// Finds the loop gain that makes the tail decay by 60 dB in `decay_time` seconds, for a
+/// loop that takes `loop_seconds` per trip.
+fn feedback_for_decay_time(loop_seconds: f32, decay_time: f32) -> f32 {
+    10f32.powf(-3.0 * loop_seconds / decay_time)
+}
+
+/// Scales the side of a stereo pair by `width`, so 0 is mono and 1 leaves it unchanged.
+fn apply_width([left, right]: [f32; 2], width: f32) -> [f32; 2] {
+    let mid = (left + right) * 0.5;
+    let side = (left - right) * 0.5 * width;
+    [mid + side, mid - side]
+}
 
 impl Reverb {
-    #[allow(clippy::cast_possible_truncation)]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
     pub fn new(env: &ProcessingEnvironment) -> Self {
         const DIFFUSER_DELAYS_MS: [f32; BLOCKS] = [20.0, 20.0, 20.0, 40.0, 80.0, 80.0, 80.0, 160.0];
         const FEEDBACK_LOOP_MIN_DELAY_MS: f32 = 100.0;
         const FEEDBACK_LOOP_MAX_DELAY_MS: f32 = 200.0;
-        const MODULATION_DEPTH_SECONDS: f32 = 0.02;
-        const MODULATION_RATE_HZ: f32 = 0.02;
-
-        let min_feedback_delay_samples =
-            (FEEDBACK_LOOP_MIN_DELAY_MS / 1000.0 * env.sampling_rate).round() as usize;
-        let feedback_delay_range_samples = ((FEEDBACK_LOOP_MAX_DELAY_MS
-            - FEEDBACK_LOOP_MIN_DELAY_MS)
-            / 1000.0
-            * env.sampling_rate)
-            .round() as usize;
 
         let mut rng = Xoshiro256PlusPlus::seed_from_u64(369);
 
+        // We choose delays in time rather than samples, so the same seed gives an
+        // equivalent reverb at any sampling rate.
+        let block_range_ms =
+            (FEEDBACK_LOOP_MAX_DELAY_MS - FEEDBACK_LOOP_MIN_DELAY_MS) / CHANNELS as f32;
         let fdn_delays = core::array::from_fn(|i| {
-            let min_for_block =
-                min_feedback_delay_samples + i * feedback_delay_range_samples / CHANNELS;
-            let max_for_block = min_for_block + feedback_delay_range_samples / CHANNELS;
-            rng.gen_range(min_for_block..max_for_block)
+            let min_for_block = FEEDBACK_LOOP_MIN_DELAY_MS + i as f32 * block_range_ms;
+            let delay_ms = rng.gen_range(min_for_block..min_for_block + block_range_ms);
+            (delay_ms / 1000.0 * env.sampling_rate).round() as usize
         });
 
+        let max_pre_delay_samples = (MAX_PRE_DELAY_SECONDS * env.sampling_rate).ceil() as usize;
+
         Self {
+            pre_delays: core::array::from_fn(|_| PerSampleDelay::new(max_pre_delay_samples)),
             diffuser: Diffuser::new(
                 &mut rng,
                 DIFFUSER_DELAYS_MS.map(|d| (d / 1000.0 * env.sampling_rate).round() as usize),
+                MAX_SIZE,
+            ),
+            feedback_loop: MultiChannelFeedbackLoop::new(
+                fdn_delays,
+                env.sampling_rate,
+                // When rendering offline we can afford higher quality interpolation.
+                match env.processing_mode {
+                    ProcessingMode::Offline => Interpolation::Cubic,
+                    ProcessingMode::Realtime | ProcessingMode::Prefetch => Interpolation::Linear,
+                },
+                MAX_SIZE,
             ),
-            feedback_loop: MultiChannelFeedbackLoop::new(fdn_delays, env.sampling_rate),
-            shelves: [Svf::default(), Svf::default()],
+            shelves: core::array::from_fn(|_| Svf::default()),
             shelf_g: calc_g(f64::from((SHELF_FREQ / env.sampling_rate).min(0.45))),
             shelf_two_r: calc_two_r(SHELF_Q),
-            modulation_depth: MODULATION_DEPTH_SECONDS * env.sampling_rate,
-            modulation_rate: MODULATION_RATE_HZ / env.sampling_rate,
+            low_cuts: core::array::from_fn(|_| Svf::default()),
+            high_cuts: core::array::from_fn(|_| Svf::default()),
+            cut_two_r: calc_two_r(CUT_Q),
+            sampling_rate: env.sampling_rate,
+            freeze: SlewLimiter::default(),
+            freeze_rate: rate_from_time(FREEZE_RAMP_SECONDS, env.sampling_rate),
+            size: SlewLimiter::default(),
+            size_rate: rate_from_time(SIZE_SLEW_SECONDS, env.sampling_rate),
+            ducker: Ducker::default(),
+            gate: Gate::default(),
+            reverser: Reverser::new((MAX_GATE_HOLD_SECONDS * env.sampling_rate).ceil() as usize),
+            convolvers: core::array::from_fn(|_| Convolver::new(&[], CONVOLUTION_BLOCK_SIZE)),
+            engine: Engine::default(),
+            smoothers: Smoothers::default(),
+            smoothing_coeff: coeff_from_time(PARAM_SMOOTHING_SECONDS, env.sampling_rate),
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn pre_delay_samples(&self, pre_delay: f32) -> f32 {
+        (pre_delay * self.sampling_rate).clamp(0.0, self.pre_delays[0].get_delay() as f32)
+    }
+
+    /// Smooths the parameters for one sample, returning them along with
+    /// the pre-delay in samples and the current freeze amount.
+    fn next_params(&mut self, params: &Params) -> (Params, f32, f32) {
+        let mut params = self.smoothers.process(params, self.smoothing_coeff);
+        params.size = self
+            .size
+            .process(params.size.clamp(MIN_SIZE, MAX_SIZE), self.size_rate);
+        let pre_delay = self.pre_delay_samples(params.pre_delay);
+        let freeze = self
+            .freeze
+            .process(if params.freeze { 1.0 } else { 0.0 }, self.freeze_rate);
+        (params, pre_delay, freeze)
+    }
+
+    /// Follows the level of the dry input to find the gain for the wet signal,
+    /// from ducking and the gated mode.
+    fn wet_gain(&mut self, params: &Params, input: &[f32]) -> f32 {
+        let level = input.iter().fold(0.0, |max, x| x.abs().max(max));
+        let duck = self.ducker.process(
+            level,
+            &ducker::Params {
+                amount: params.duck_amount,
+                attack: params.duck_attack,
+                release: params.duck_release,
+            },
+            self.sampling_rate,
+        );
+        let gate = self.gate.process(
+            level,
+            &gate::Params {
+                mode: params.mode,
+                hold: params.gate_hold,
+                release: params.gate_release,
+                threshold: params.gate_threshold,
+            },
+            self.sampling_rate,
+        );
+        duck * gate
+    }
+
+    /// Process one frame of `N` reverb channels.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn process_frame<const N: usize>(
+        &mut self,
+        params: &Params,
+        pre_delay: f32,
+        freeze: f32,
+        input: &[f32; N],
+    ) -> [f32; N] {
+        let wet_gain = self.wet_gain(params, input);
+        #[allow(clippy::cast_precision_loss)]
+        let reverb_input = if params.mono_input {
+            [input.iter().sum::<f32>() / N as f32; N]
+        } else {
+            *input
+        };
+        if params.engine != self.engine {
+            // Start the new engine from silence, rather than from wherever it was
+            // when we last switched away from it.
+            match params.engine {
+                Engine::Algorithmic => self.reset_algorithmic(),
+                Engine::Convolution => self.reset_convolution(),
+            }
+            self.reverser.reset();
+            self.engine = params.engine;
+        }
+        let mut wet = match params.engine {
+            Engine::Algorithmic => {
+                self.process_algorithmic(params, pre_delay, freeze, &reverb_input)
+            }
+            Engine::Convolution => core::array::from_fn(|channel| {
+                self.convolvers[channel].process(reverb_input[channel])
+            }),
+        };
+        if params.mode == gate::Mode::Reverse {
+            wet = self.reverser.process(
+                &wet,
+                (params.gate_hold * self.sampling_rate).round() as usize,
+                params.gate_release * self.sampling_rate,
+            );
         }
+        core::array::from_fn(|channel| {
+            params.mix * wet_gain * wet[channel] + (1.0 - params.mix) * input[channel]
+        })
     }
 
+    /// Process one frame of `N` channels through the algorithmic reverb, returning the
+    /// wet signal.
     #[allow(clippy::cast_possible_truncation)]
-    pub fn process(&mut self, params: &Params, input: &impl Buffer, output: &mut impl BufferMut) {
-        if input.num_channels() == 1 {
-            let input = input.channel(0);
-            let output = output.channel_mut(0);
-            for (input, output) in input.iter().zip(output.iter_mut()) {
-                let mc_input = [*input; CHANNELS];
-                let (x, er) =
-                    self.diffuser
-                        .process_mono(params.early_reflections, params.density, &mc_input);
-                *output = self.feedback_loop.process(
-                    x,
-                    params.feedback,
-                    params.damping,
-                    self.modulation_depth,
-                    self.modulation_rate,
-                )[0] + er;
-                *output = params.mix
-                    * self.shelves[0]
-                        .process_high_shelf(std::iter::once(GainInput {
-                            x: f64::from(*output),
-                            params: GainRawParams {
-                                g: self.shelf_g,
-                                two_r: self.shelf_two_r,
-                                sqrt_gain: f64::from(params.brightness),
-                            },
-                        }))
-                        .next()
-                        .unwrap() as f32
-                    + (1.0 - params.mix) * *input;
+    fn process_algorithmic<const N: usize>(
+        &mut self,
+        params: &Params,
+        pre_delay: f32,
+        freeze: f32,
+        input: &[f32; N],
+    ) -> [f32; N] {
+        let mut wet = [0.0; N];
+        for ((wet, input), line) in wet.iter_mut().zip(*input).zip(&mut self.pre_delays) {
+            *wet = process_pre_delay(line, input, pre_delay) * (1.0 - freeze);
+        }
+        // Spread the input channels evenly across the diffuser channels.
+        let mc_input = core::array::from_fn(|i| wet[i % N]);
+        let (x, er) = self.diffuser.process_multichannel::<N>(
+            params.early_reflections,
+            params.density,
+            params.size,
+            &mc_input,
+        );
+        let y = self.feedback_loop.process(
+            x,
+            feedback_for_decay_time(
+                self.feedback_loop.average_delay(params.size) / self.sampling_rate,
+                params.decay_time,
+            ),
+            &Damping {
+                high: params.damping,
+                bass_multiplier: params.bass_multiplier,
+                bass_crossover: params.bass_crossover / self.sampling_rate,
+            },
+            &Modulation {
+                depth: params.modulation_depth * self.sampling_rate,
+                rate: params.modulation_rate / self.sampling_rate,
+                all_lines: params.modulate_all_lines,
+            },
+            Shimmer {
+                amount: params.shimmer_amount,
+                ratio: params.shimmer_interval.ratio(),
+            },
+            freeze,
+            params.size,
+        );
+        let low_cut = RawParams {
+            g: calc_g(f64::from((params.low_cut / self.sampling_rate).min(0.45))),
+            two_r: self.cut_two_r,
+        };
+        let high_cut = RawParams {
+            g: calc_g(f64::from((params.high_cut / self.sampling_rate).min(0.45))),
+            two_r: self.cut_two_r,
+        };
+        let mut wet: [f32; N] = core::array::from_fn(|channel| {
+            let wet = self.shelves[channel]
+                .process_high_shelf(std::iter::once(GainInput {
+                    x: f64::from(y[channel] + er[channel]),
+                    params: GainRawParams {
+                        g: self.shelf_g,
+                        two_r: self.shelf_two_r,
+                        sqrt_gain: f64::from(params.brightness),
+                    },
+                }))
+                .next()
+                .unwrap();
+            let wet = self.low_cuts[channel]
+                .process_high(std::iter::once(Input {
+                    x: wet,
+                    params: low_cut,
+                }))
+                .next()
+                .unwrap();
+            self.high_cuts[channel]
+                .process_low(std::iter::once(Input {
+                    x: wet,
+                    params: high_cut,
+                }))
+                .next()
+                .unwrap() as f32
+        });
+        if let [left, right] = &mut wet[..] {
+            [*left, *right] = apply_width([*left, *right], params.width);
+        }
+        wet
+    }
+
+    /// Process the buffer channels listed in `channels` through the reverb.
+    fn process_channels<const N: usize>(
+        &mut self,
+        params: impl IntoIterator<Item = Params>,
+        input: &impl Buffer,
+        output: &mut impl BufferMut,
+        channels: [usize; N],
+    ) {
+        for (i, params) in (0..input.num_frames()).zip(params) {
+            let (params, pre_delay, freeze) = self.next_params(&params);
+            let frame = self.process_frame(
+                &params,
+                pre_delay,
+                freeze,
+                &channels.map(|channel| input.channel(channel)[i]),
+            );
+            for (channel, sample) in channels.iter().zip(frame) {
+                output.channel_mut(*channel)[i] = sample;
             }
-        } else if input.num_channels() == 2 {
-            let input_l = input.channel(0);
-            let input_r = input.channel(1);
-            for (i, (input_l, input_r)) in input_l.iter().zip(input_r.iter()).enumerate() {
-                {
-                    let mc_input =
-                        core::array::from_fn(|i| if i & 1 == 0 { *input_l } else { *input_r });
-                    let (x, er) = self.diffuser.process_stereo(
-                        params.early_reflections,
-                        params.density,
-                        &mc_input,
-                    );
-                    let y = self.feedback_loop.process(
-                        x,
-                        params.feedback,
-                        params.damping,
-                        self.modulation_depth,
-                        self.modulation_rate,
-                    );
-                    for channel in 0..2 {
-                        output.channel_mut(channel)[i] = params.mix
-                            * self.shelves[channel]
-                                .process_high_shelf(std::iter::once(GainInput {
-                                    x: f64::from(y[channel] + er[channel]),
-                                    params: GainRawParams {
-                                        g: self.shelf_g,
-                                        two_r: self.shelf_two_r,
-                                        sqrt_gain: f64::from(params.brightness),
-                                    },
-                                }))
-                                .next()
-                                .unwrap() as f32
-                            + (1.0 - params.mix) * (if channel == 0 { *input_l } else { *input_r });
-                    }
-                }
+        }
+    }
+
+    /// Process a buffer, where `params` yields the parameters for each sample.
+    ///
+    /// We support mono, stereo, quad (L, R, Ls, Rs), 5.0 (L, R, C, Ls, Rs) and
+    /// 5.1 (L, R, C, LFE, Ls, Rs) buffers. Each channel gets its own decorrelated
+    /// output from the reverb, except for the LFE channel which passes through dry.
+    pub fn process(
+        &mut self,
+        params: impl IntoIterator<Item = Params>,
+        input: &impl Buffer,
+        output: &mut impl BufferMut,
+    ) {
+        match input.num_channels() {
+            1 => self.process_channels(params, input, output, [0]),
+            2 => self.process_channels(params, input, output, [0, 1]),
+            4 => self.process_channels(params, input, output, [0, 1, 2, 3]),
+            5 => self.process_channels(params, input, output, [0, 1, 2, 3, 4]),
+            6 => {
+                self.process_channels(params, input, output, [0, 1, 2, 4, 5]);
+                output
+                    .channel_mut(LFE_CHANNEL)
+                    .copy_from_slice(input.channel(LFE_CHANNEL));
             }
-        } else {
-            panic!("Reverb only supports mono and stereo input");
+            _ => panic!("Reverb only supports mono, stereo, quad, 5.0, and 5.1 input"),
         }
     }
 
-    pub fn reset(&mut self) {
+    /// Loads the impulse response used by the convolution engine, with one slice per
+    /// channel. If there are fewer impulse responses than channels, they are repeated.
+    ///
+    /// Note that this allocates, so should not be called on the audio thread.
+    pub fn load_impulse_response(&mut self, channels: &[impl AsRef<[f32]>]) {
+        for (i, convolver) in self.convolvers.iter_mut().enumerate() {
+            *convolver = Convolver::new(
+                channels
+                    .get(i % channels.len().max(1))
+                    .map_or(&[], AsRef::as_ref),
+                CONVOLUTION_BLOCK_SIZE,
+            );
+        }
+    }
+
+    fn reset_algorithmic(&mut self) {
+        for pre_delay in &mut self.pre_delays {
+            pre_delay.reset();
+        }
         self.diffuser.reset();
         self.feedback_loop.reset();
-        for shelf in &mut self.shelves {
-            shelf.reset();
+        for filter in self
+            .shelves
+            .iter_mut()
+            .chain(&mut self.low_cuts)
+            .chain(&mut self.high_cuts)
+        {
+            filter.reset();
+        }
+    }
+
+    fn reset_convolution(&mut self) {
+        for convolver in &mut self.convolvers {
+            convolver.reset();
         }
     }
+
+    pub fn reset(&mut self) {
+        self.reset_algorithmic();
+        self.reset_convolution();
+        self.freeze.reset();
+        self.size.reset();
+        self.ducker.reset();
+        self.gate.reset();
+        self.reverser.reset();
+        self.smoothers.reset();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
     use conformal_component::{
         ProcessingMode,
-        audio::{BufferData, ChannelLayout},
+        audio::{BufferData, ChannelLayout, all_approx_eq},
     };
     use snapshots::assert_snapshot;
 
-    fn impulse_response_for_params(params: &Params) -> Vec<f32> {
-        const SNAPSHOT_LENGTH: usize = 48_000;
-        const SAMPLING_RATE: f32 = 48000.0;
+    /// A fully wet, undamped reverb. Tests override just the parameters they exercise.
+    fn test_params() -> Params {
+        Params {
+            decay_time: 2.0,
+            damping: 1.0,
+            bass_multiplier: 1.0,
+            bass_crossover: 250.0,
+            brightness: 1.0,
+            mix: 1.0,
+            pre_delay: 0.0,
+            early_reflections: 0.5,
+            density: 1.0,
+            low_cut: LOW_CUT_OFF,
+            high_cut: HIGH_CUT_OFF,
+            size: 1.0,
+            width: 1.0,
+            mono_input: false,
+            modulation_depth: 0.02,
+            modulation_rate: 0.02,
+            modulate_all_lines: false,
+            shimmer_amount: 0.0,
+            shimmer_interval: pitch_shifter::Interval::OctaveUp,
+            duck_amount: 0.0,
+            duck_attack: 0.01,
+            duck_release: 0.25,
+            mode: gate::Mode::Natural,
+            gate_hold: 0.3,
+            gate_release: 0.05,
+            gate_threshold: 0.01,
+            freeze: false,
+            engine: Engine::Algorithmic,
+        }
+    }
+
+    fn process_at_rate(input: &BufferData, params: &Params, sampling_rate: f32) -> BufferData {
         let mut reverb = Reverb::new(&ProcessingEnvironment {
-            sampling_rate: SAMPLING_RATE,
-            max_samples_per_process_call: SNAPSHOT_LENGTH,
-            channel_layout: ChannelLayout::Mono,
+            sampling_rate,
+            max_samples_per_process_call: input.num_frames(),
+            channel_layout: input.channel_layout(),
             processing_mode: ProcessingMode::Realtime,
         });
-        let mut impulse_vec = vec![0.0; SNAPSHOT_LENGTH];
-        impulse_vec[0] = 1.0;
-        let mut output = BufferData::new_mono(vec![0.0; SNAPSHOT_LENGTH]);
-        reverb.process(params, &BufferData::new_mono(impulse_vec), &mut output);
-        output.channel(0).to_vec()
+        let mut output = BufferData::new(input.channel_layout(), input.num_frames());
+        reverb.process(std::iter::repeat(*params), input, &mut output);
+        output
+    }
+
+    fn process_mono(input: Vec<f32>, params: &Params) -> Vec<f32> {
+        process_at_rate(&BufferData::new_mono(input), params, 48000.0)
+            .channel(0)
+            .to_vec()
+    }
+
+    fn process_stereo([left, right]: [Vec<f32>; 2], params: &Params) -> BufferData {
+        process_at_rate(&BufferData::new_stereo(left, right), params, 48000.0)
+    }
+
+    fn impulse_response_at_rate(params: &Params, sampling_rate: f32, length: usize) -> Vec<f32> {
+        let mut impulse = vec![0.0; length];
+        impulse[0] = 1.0;
+        process_at_rate(&BufferData::new_mono(impulse), params, sampling_rate)
+            .channel(0)
+            .to_vec()
+    }
+
+    fn impulse_response_for_params(params: &Params) -> Vec<f32> {
+        impulse_response_at_rate(params, 48000.0, 48_000)
     }
 
     #[test]
@@ -183,12 +711,8 @@ mod tests {
             "reverb/impulse_response",
             48000,
             impulse_response_for_params(&Params {
-                feedback: 0.6,
-                damping: 1.0,
-                brightness: 1.0,
-                mix: 1.0,
                 early_reflections: 0.0,
-                density: 1.0,
+                ..test_params()
             })
         );
     }
@@ -200,13 +724,636 @@ mod tests {
             "reverb/impulse_response_modulated_damped",
             48000,
             impulse_response_for_params(&Params {
-                feedback: 0.6,
                 damping: 0.5,
                 brightness: 0.5,
-                mix: 1.0,
                 early_reflections: 0.0,
-                density: 1.0,
+                ..test_params()
             })
         );
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn pre_delay_offsets_response() {
+        const PRE_DELAY_SAMPLES: usize = 4800;
+        let params = |pre_delay| Params {
+            pre_delay,
+            early_reflections: 0.0,
+            ..test_params()
+        };
+        let response = impulse_response_for_params(&params(0.1));
+        assert!(response[..PRE_DELAY_SAMPLES].iter().all(|x| x.abs() < 1e-9));
+        let undelayed = impulse_response_for_params(&params(0.0));
+        let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        assert!(energy(&undelayed[..PRE_DELAY_SAMPLES]) > 1e-3);
+        assert!(energy(&response[PRE_DELAY_SAMPLES..]) > 1e-3);
+    }
+
+    #[test]
+    fn fractional_pre_delay_interpolates() {
+        let mut line = PerSampleDelay::new(4);
+        let output: Vec<_> = [1.0, 0.0, 0.0, 0.0]
+            .into_iter()
+            .map(|x| process_pre_delay(&mut line, x, 1.5))
+            .collect();
+        assert!(all_approx_eq(output, [0.0, 0.5, 0.5, 0.0], 1e-6));
+
+        line.reset();
+        let output: Vec<_> = [1.0, 0.0, 0.0]
+            .into_iter()
+            .map(|x| process_pre_delay(&mut line, x, 0.25))
+            .collect();
+        assert!(all_approx_eq(output, [0.75, 0.25, 0.0], 1e-6));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn size_scales_response() {
+        let params = |size| Params {
+            early_reflections: 0.0,
+            size,
+            ..test_params()
+        };
+        let onset = |x: &[f32]| x.iter().position(|x| x.abs() > 1e-3).unwrap();
+        let small = impulse_response_for_params(&params(MIN_SIZE));
+        let default = impulse_response_for_params(&params(1.0));
+        let large = impulse_response_for_params(&params(MAX_SIZE));
+        assert!(onset(&small) < onset(&default));
+        assert!(onset(&default) < onset(&large));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn size_changes_are_smooth() {
+        const SAMPLING_RATE: f32 = 48000.0;
+        const LENGTH: usize = 48000;
+        let mut reverb = Reverb::new(&ProcessingEnvironment {
+            sampling_rate: SAMPLING_RATE,
+            max_samples_per_process_call: LENGTH,
+            channel_layout: ChannelLayout::Mono,
+            processing_mode: ProcessingMode::Realtime,
+        });
+        let input = dsp::test_utils::sine(LENGTH, 220.0 / SAMPLING_RATE);
+        let mut output = BufferData::new_mono(vec![0.0; LENGTH]);
+        // Jump between the smallest and largest sizes every 100ms.
+        reverb.process(
+            (0..LENGTH).map(|i| Params {
+                size: if (i / 4800) % 2 == 0 {
+                    MIN_SIZE
+                } else {
+                    MAX_SIZE
+                },
+                ..test_params()
+            }),
+            &BufferData::new_mono(input),
+            &mut output,
+        );
+        let output = output.channel(0);
+        assert!(output.iter().all(|x| x.is_finite()));
+        let max_step = output
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max);
+        let max = output.iter().map(|x| x.abs()).fold(0.0, f32::max);
+        assert!(max_step < max * 0.2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn ducks_while_input_is_active() {
+        const BURST: usize = 24000;
+        let params = |duck_amount| Params {
+            decay_time: 10.0,
+            duck_amount,
+            duck_release: 0.1,
+            ..test_params()
+        };
+        let mut input = dsp::test_utils::white_noise(BURST);
+        input.resize(BURST * 4, 0.0);
+        let ducked = process_mono(input.clone(), &params(1.0));
+        let unducked = process_mono(input, &params(0.0));
+        let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        let during = BURST / 2..BURST;
+        assert!(energy(&ducked[during.clone()]) < energy(&unducked[during]) * 0.01);
+        let after = BURST * 3..BURST * 4;
+        assert_approx_eq!(
+            energy(&ducked[after.clone()]) / energy(&unducked[after]),
+            1.0,
+            0.05
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn gated_mode_closes_tail() {
+        let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        let response = |mode| {
+            impulse_response_for_params(&Params {
+                decay_time: 10.0,
+                mode,
+                ..test_params()
+            })
+        };
+        let natural = response(gate::Mode::Natural);
+        let gated = response(gate::Mode::Gated);
+        assert!(all_approx_eq(
+            natural[..14400].iter().copied(),
+            gated[..14400].iter().copied(),
+            1e-6
+        ));
+        assert!(energy(&natural[24000..]) > 1e-3);
+        assert!(energy(&gated[24000..]) < 1e-12);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn reverse_mode_swells() {
+        // The gate hold from `test_params`, in samples.
+        const SEGMENT: usize = 14400;
+        let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        let response = |mode| {
+            impulse_response_for_params(&Params {
+                decay_time: 10.0,
+                mode,
+                ..test_params()
+            })
+        };
+        let natural = response(gate::Mode::Natural);
+        let reverse = response(gate::Mode::Reverse);
+        assert!(energy(&natural[..3600]) > energy(&natural[10800..14400]));
+
+        // The first segment is recorded before anything plays back.
+        assert!(energy(&reverse[..SEGMENT]) < 1e-12);
+        assert!(
+            energy(&reverse[SEGMENT..SEGMENT + 3600])
+                < energy(&reverse[2 * SEGMENT - 3600..2 * SEGMENT])
+        );
+
+        // Then it plays back reversed, under the swell envelope.
+        #[allow(clippy::cast_precision_loss)]
+        for i in [3600, 7200, 10800] {
+            let swell = (i + 1) as f32 / SEGMENT as f32;
+            assert_approx_eq!(
+                reverse[SEGMENT + i],
+                natural[SEGMENT - 1 - i] * swell * swell,
+                1e-6
+            );
+        }
+    }
+
+    fn noise_response_for_cuts(low_cut: f32, high_cut: f32) -> Vec<f32> {
+        process_mono(
+            dsp::test_utils::white_noise(16384),
+            &Params {
+                low_cut,
+                high_cut,
+                ..test_params()
+            },
+        )
+    }
+
+    /// Returns the power gain of the cut filters in each frequency band, in Hz.
+    fn cut_response<const N: usize>(
+        low_cut: f32,
+        high_cut: f32,
+        bands: [std::ops::Range<f32>; N],
+    ) -> [f32; N] {
+        cut_response_relative_to((LOW_CUT_OFF, HIGH_CUT_OFF), (low_cut, high_cut), bands)
+    }
+
+    /// Like `cut_response`, but relative to the given reference cuts rather than the
+    /// widest ones.
+    #[allow(clippy::cast_precision_loss)]
+    fn cut_response_relative_to<const N: usize>(
+        (reference_low_cut, reference_high_cut): (f32, f32),
+        (low_cut, high_cut): (f32, f32),
+        bands: [std::ops::Range<f32>; N],
+    ) -> [f32; N] {
+        let mut reference = noise_response_for_cuts(reference_low_cut, reference_high_cut);
+        let mut filtered = noise_response_for_cuts(low_cut, high_cut);
+        let bin_hz = 48000.0 / reference.len() as f32;
+        let power_spectrum = |x: &mut [f32]| {
+            dsp::test_utils::windowed_rfft(x)
+                .iter()
+                .map(|x| x.re * x.re + x.im * x.im)
+                .collect::<Vec<_>>()
+        };
+        let reference = power_spectrum(&mut reference);
+        let filtered = power_spectrum(&mut filtered);
+        let power = |spectrum: &[f32], band: &std::ops::Range<f32>| {
+            spectrum
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| band.contains(&(*i as f32 * bin_hz)))
+                .map(|(_, x)| x)
+                .sum::<f32>()
+        };
+        bands.map(|band| power(&filtered, &band) / power(&reference, &band))
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn low_cut_removes_lows() {
+        let [low, high] = cut_response(1000.0, HIGH_CUT_OFF, [50.0..200.0, 5000.0..10000.0]);
+        assert!(low < 0.01);
+        assert_approx_eq!(high, 1.0, 0.05);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn high_cut_removes_highs() {
+        let [low, high] = cut_response(LOW_CUT_OFF, 1000.0, [50.0..200.0, 5000.0..10000.0]);
+        assert_approx_eq!(low, 1.0, 0.05);
+        assert!(high < 0.01);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn cuts_are_transparent_at_extremes() {
+        // A low cut at 0 Hz passes everything, and the high cut saturates below nyquist.
+        let [low, high] = cut_response_relative_to(
+            (0.0, 24000.0),
+            (LOW_CUT_OFF, HIGH_CUT_OFF),
+            [30.0..200.0, 5000.0..15000.0],
+        );
+        assert_approx_eq!(low, 1.0, 0.05);
+        assert_approx_eq!(high, 1.0, 0.05);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn freeze_holds_energy() {
+        const SAMPLING_RATE: f32 = 48000.0;
+        const WINDOW: usize = 48000;
+        let mut reverb = Reverb::new(&ProcessingEnvironment {
+            sampling_rate: SAMPLING_RATE,
+            max_samples_per_process_call: WINDOW,
+            channel_layout: ChannelLayout::Mono,
+            processing_mode: ProcessingMode::Realtime,
+        });
+        let params = |freeze| Params {
+            damping: 0.5,
+            freeze,
+            ..test_params()
+        };
+        let mut process = |input: Vec<f32>, freeze| {
+            let mut output = BufferData::new_mono(vec![0.0; input.len()]);
+            reverb.process(
+                std::iter::repeat(params(freeze)),
+                &BufferData::new_mono(input),
+                &mut output,
+            );
+            output.channel(0).to_vec()
+        };
+        let energy = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+
+        process(dsp::test_utils::white_noise(WINDOW / 2), false);
+
+        // New input should be ignored while frozen, so feed in more noise.
+        // Skip the first window to let the early reflections die out.
+        process(dsp::test_utils::white_noise(WINDOW), true);
+        let reference = energy(&process(dsp::test_utils::white_noise(WINDOW), true));
+        assert!(reference > 1.0);
+        for _ in 0..4 {
+            let frozen = energy(&process(dsp::test_utils::white_noise(WINDOW), true));
+            assert_approx_eq!(frozen / reference, 1.0, 0.05);
+        }
+
+        // Once we release freeze, the tail should decay.
+        process(vec![0.0; WINDOW], false);
+        let released = energy(&process(vec![0.0; WINDOW], false));
+        assert!(released < reference * 0.1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn full_shimmer_with_long_decay_is_bounded() {
+        const BURST: usize = 4800;
+        const LENGTH: usize = 48000 * 20;
+        const WINDOW: usize = 48000;
+        for shimmer_interval in [
+            pitch_shifter::Interval::OctaveUp,
+            pitch_shifter::Interval::FifthUp,
+            pitch_shifter::Interval::OctaveDown,
+        ] {
+            let mut input = dsp::test_utils::white_noise(BURST);
+            input.resize(LENGTH, 0.0);
+            let output = process_mono(
+                input,
+                &Params {
+                    decay_time: 45.0,
+                    bass_multiplier: 2.0,
+                    shimmer_amount: 1.0,
+                    shimmer_interval,
+                    ..test_params()
+                },
+            );
+            assert!(output.iter().all(|x| x.is_finite()));
+            assert!(output.iter().all(|x| x.abs() < 2.0));
+
+            // Once the burst has passed, the tail should never build back up.
+            let energies = output
+                .chunks(WINDOW)
+                .map(|x| x.iter().map(|x| x * x).sum::<f32>())
+                .collect::<Vec<_>>();
+            assert!(energies[2..].iter().all(|e| *e <= energies[1]));
+            assert!(energies[energies.len() - 1] < energies[1] * 0.01);
+        }
+    }
+
+    /// A buffer with an arbitrary number of channels, for testing surround layouts.
+    struct SurroundBuffer {
+        channels: Vec<Vec<f32>>,
+    }
+
+    impl Buffer for SurroundBuffer {
+        fn channel_layout(&self) -> ChannelLayout {
+            // `ChannelLayout` can't describe surround layouts, so we override `num_channels`
+            ChannelLayout::Stereo
+        }
+
+        fn num_channels(&self) -> usize {
+            self.channels.len()
+        }
+
+        fn num_frames(&self) -> usize {
+            self.channels[0].len()
+        }
+
+        fn channel(&self, channel: usize) -> &[f32] {
+            &self.channels[channel]
+        }
+    }
+
+    impl BufferMut for SurroundBuffer {
+        fn channel_mut(&mut self, channel: usize) -> &mut [f32] {
+            &mut self.channels[channel]
+        }
+    }
+
+    fn process_surround(input: Vec<Vec<f32>>) -> SurroundBuffer {
+        let num_frames = input[0].len();
+        let mut reverb = Reverb::new(&ProcessingEnvironment {
+            sampling_rate: 48000.0,
+            max_samples_per_process_call: num_frames,
+            channel_layout: ChannelLayout::Stereo,
+            processing_mode: ProcessingMode::Realtime,
+        });
+        let mut output = SurroundBuffer {
+            channels: vec![vec![0.0; num_frames]; input.len()],
+        };
+        reverb.process(
+            std::iter::repeat(test_params()),
+            &SurroundBuffer { channels: input },
+            &mut output,
+        );
+        output
+    }
+
+    fn correlation(a: &[f32], b: &[f32]) -> f32 {
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn quad_outputs_are_decorrelated() {
+        let mut impulse = vec![0.0; 48000];
+        impulse[0] = 1.0;
+        let output = process_surround(vec![impulse; 4]);
+        for a in 0..4 {
+            for b in (a + 1)..4 {
+                assert!(correlation(output.channel(a), output.channel(b)).abs() < 0.2);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn surround_lfe_passes_through() {
+        let input: Vec<_> = (0..6).map(|_| dsp::test_utils::white_noise(4800)).collect();
+        let output = process_surround(input.clone());
+        assert!(all_approx_eq(
+            output.channel(3).iter().copied(),
+            input[3].iter().copied(),
+            1e-6
+        ));
+        for channel in [0, 1, 2, 4, 5] {
+            assert!(output.channel(channel).iter().any(|x| x.abs() > 1e-3));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn zero_width_is_mono() {
+        let noise = dsp::test_utils::white_noise(48000);
+        let output = process_stereo(
+            [noise.clone(), noise],
+            &Params {
+                width: 0.0,
+                ..test_params()
+            },
+        );
+        assert_approx_eq!(correlation(output.channel(0), output.channel(1)), 1.0, 1e-4);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn full_width_is_decorrelated() {
+        let noise = dsp::test_utils::white_noise(48000);
+        let output = process_stereo([noise.clone(), noise], &test_params());
+        assert!(correlation(output.channel(0), output.channel(1)).abs() < 0.2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn extra_width_is_anticorrelated() {
+        let noise = dsp::test_utils::white_noise(48000);
+        let full = process_stereo([noise.clone(), noise.clone()], &test_params());
+        let extra = process_stereo(
+            [noise.clone(), noise],
+            &Params {
+                width: 2.0,
+                ..test_params()
+            },
+        );
+        assert!(
+            correlation(extra.channel(0), extra.channel(1))
+                < correlation(full.channel(0), full.channel(1))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn mono_input_sums_channels() {
+        let noise = dsp::test_utils::white_noise(48000);
+        let half: Vec<_> = noise.iter().map(|x| x * 0.5).collect();
+        let left_only = process_stereo(
+            [noise.clone(), vec![0.0; noise.len()]],
+            &Params {
+                mono_input: true,
+                ..test_params()
+            },
+        );
+        let centered = process_stereo([half.clone(), half], &test_params());
+        for channel in 0..2 {
+            assert!(all_approx_eq(
+                left_only.channel(channel).iter().copied(),
+                centered.channel(channel).iter().copied(),
+                1e-6
+            ));
+        }
+    }
+
+    /// Estimates RT60 from an impulse response, by extrapolating the time the
+    /// Schroeder energy decay curve takes to fall from -5 dB to -35 dB.
+    #[allow(clippy::cast_precision_loss)]
+    fn estimate_rt60(impulse_response: &[f32], sampling_rate: f32) -> f32 {
+        // We integrate backwards from the end, so the late part of the curve
+        // doesn't suffer from cancellation.
+        let mut decay_curve = impulse_response
+            .iter()
+            .rev()
+            .scan(0.0, |remaining, x| {
+                *remaining += f64::from(x * x);
+                Some(*remaining)
+            })
+            .collect::<Vec<_>>();
+        decay_curve.reverse();
+        let total = decay_curve[0];
+        let crossing = |db: f32| {
+            decay_curve
+                .iter()
+                .position(|x| *x < total * 10f64.powf(f64::from(db) / 10.0))
+                .unwrap()
+        };
+        let start = crossing(-5.0);
+        let end = crossing(-35.0);
+        // We only measured a 30 dB decay, so double it.
+        2.0 * (end - start) as f32 / sampling_rate
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn rt60_for_params(params: &Params, sampling_rate: f32) -> f32 {
+        // Render long enough that the end of the tail is far below the range we measure.
+        let length = (params.decay_time * 2.0 * sampling_rate) as usize;
+        estimate_rt60(
+            &impulse_response_at_rate(params, sampling_rate, length),
+            sampling_rate,
+        )
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn rt60_matches_decay_time() {
+        for sampling_rate in [44100.0, 48000.0, 96000.0] {
+            for decay_time in [1.0, 2.5] {
+                let rt60 = rt60_for_params(
+                    &Params {
+                        decay_time,
+                        modulation_depth: 0.0,
+                        ..test_params()
+                    },
+                    sampling_rate,
+                );
+                assert_approx_eq!(rt60, decay_time, decay_time * 0.1);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn rt60_is_independent_of_size() {
+        for size in [MIN_SIZE, 0.5, MAX_SIZE] {
+            let rt60 = rt60_for_params(
+                &Params {
+                    size,
+                    modulation_depth: 0.0,
+                    ..test_params()
+                },
+                48000.0,
+            );
+            assert_approx_eq!(rt60, 2.0, 0.2);
+        }
+    }
+
+    /// Length of each window of the decay envelope, in seconds.
+    const ENVELOPE_WINDOW_SECONDS: f32 = 0.01;
+
+    /// Renders the energy of each window of the impulse response, as an amplitude.
+    ///
+    /// We sum rather than average the energy in each window, so the envelope
+    /// doesn't depend on the number of samples per window.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn decay_envelope(sampling_rate: f32) -> Vec<f32> {
+        // Keep most of the wet signal in a band that every rate can carry.
+        let params = Params {
+            decay_time: 1.5,
+            high_cut: 8000.0,
+            ..test_params()
+        };
+        let window = (ENVELOPE_WINDOW_SECONDS * sampling_rate).round() as usize;
+        impulse_response_at_rate(&params, sampling_rate, (sampling_rate * 2.0) as usize)
+            .chunks(window)
+            .map(|window| window.iter().map(|x| x * x).sum::<f32>().sqrt())
+            .collect()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn decay_envelope_snapshot(sampling_rate: f32) {
+        assert_snapshot!(
+            &format!("reverb/decay_envelope_{}", sampling_rate as i32),
+            (1.0 / ENVELOPE_WINDOW_SECONDS) as i32,
+            decay_envelope(sampling_rate)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decay_envelope_44100() {
+        decay_envelope_snapshot(44100.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decay_envelope_48000() {
+        decay_envelope_snapshot(48000.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn decay_envelope_96000() {
+        decay_envelope_snapshot(96000.0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[allow(clippy::cast_precision_loss)]
+    fn decay_envelope_is_sample_rate_independent() {
+        // The early part of the envelope is most sensitive to the exact delays.
+        const EARLY_WINDOWS: usize = 30;
+        let reference = decay_envelope(48000.0);
+        for sampling_rate in [44100.0, 96000.0] {
+            let differences = decay_envelope(sampling_rate)
+                .iter()
+                .zip(&reference)
+                .map(|(a, b)| 20.0 * (a / b).log10())
+                .collect::<Vec<_>>();
+            // Higher rates still carry some energy past the high cut, so we only compare
+            // the shape of the envelopes.
+            let offset = differences.iter().sum::<f32>() / differences.len() as f32;
+            let differences = differences
+                .iter()
+                .map(|d| (d - offset).abs())
+                .collect::<Vec<_>>();
+            assert!(differences.iter().all(|d| *d < 1.25));
+            let early_mean =
+                differences[..EARLY_WINDOWS].iter().sum::<f32>() / EARLY_WINDOWS as f32;
+            assert!(early_mean < 0.4);
+        }
+    }
 }
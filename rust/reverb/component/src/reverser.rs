@@ -0,0 +1,157 @@
+//! Segmented reverse playback for the reverse reverb mode.
+
+/// Records the wet signal in segments, and plays each finished segment back
+/// reversed while the next one records.
+///
+/// Playing a decaying tail backwards makes it swell in, and we shape each segment with
+/// a swelling envelope that closes over the release time, so segments join without
+/// clicks. Note that this delays the wet signal by one segment.
+#[derive(Debug, Clone)]
+pub struct Reverser<const N: usize> {
+    /// Two halves per channel - one being recorded, and one being played back.
+    buffers: [Vec<f32>; N],
+
+    max_segment: usize,
+
+    /// Which half of each buffer is being recorded.
+    recording: usize,
+
+    /// How far we are into the current segment, in samples.
+    position: usize,
+
+    /// Length of the segment being recorded, in samples.
+    recording_length: usize,
+
+    /// Length of the segment being played back, in samples.
+    playing_length: usize,
+}
+
+impl<const N: usize> Reverser<N> {
+    pub fn new(max_segment: usize) -> Self {
+        let max_segment = max_segment.max(1);
+        Self {
+            buffers: core::array::from_fn(|_| vec![0.0; 2 * max_segment]),
+            max_segment,
+            recording: 0,
+            position: 0,
+            recording_length: 0,
+            playing_length: 0,
+        }
+    }
+
+    /// Here `segment` is the length of each segment, and `release` is how long each
+    /// segment takes to close, both in samples.
+    ///
+    /// Changes to `segment` take effect at the start of the next segment.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn process<const M: usize>(
+        &mut self,
+        input: &[f32; M],
+        segment: usize,
+        release: f32,
+    ) -> [f32; M] {
+        debug_assert!(M <= N);
+        if self.position == 0 {
+            self.recording_length = segment.clamp(1, self.max_segment);
+        }
+
+        // If the segments changed length, we may have to cut off playback early, so we
+        // close the envelope by whichever end comes first.
+        let end = self.playing_length.min(self.recording_length);
+        let gain = if self.position < end {
+            let swell = (self.position + 1) as f32 / self.playing_length as f32;
+            let close = ((end - self.position) as f32 / release.max(1.0)).min(1.0);
+            swell * swell * close
+        } else {
+            0.0
+        };
+        let record_offset = self.recording * self.max_segment;
+        let play_offset = (1 - self.recording) * self.max_segment;
+        let output = core::array::from_fn(|channel| {
+            let buffer = &mut self.buffers[channel];
+            buffer[record_offset + self.position] = input[channel];
+            if gain > 0.0 {
+                buffer[play_offset + self.playing_length - 1 - self.position] * gain
+            } else {
+                0.0
+            }
+        });
+
+        self.position += 1;
+        if self.position >= self.recording_length {
+            self.position = 0;
+            self.recording = 1 - self.recording;
+            self.playing_length = self.recording_length;
+        }
+        output
+    }
+
+    pub fn reset(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill(0.0);
+        }
+        self.recording = 0;
+        self.position = 0;
+        self.recording_length = 0;
+        self.playing_length = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn run(reverser: &mut Reverser<1>, input: &[f32], segment: usize, release: f32) -> Vec<f32> {
+        input
+            .iter()
+            .map(|x| reverser.process(&[*x], segment, release)[0])
+            .collect()
+    }
+
+    #[test]
+    fn plays_segments_backwards() {
+        let mut reverser = Reverser::new(4);
+        let output = run(
+            &mut reverser,
+            &[1.0, 2.0, 3.0, 4.0, 0.0, 0.0, 0.0, 0.0],
+            4,
+            1.0,
+        );
+        assert!(output[..4].iter().all(|x| *x == 0.0));
+        // Each sample is scaled by the swell envelope.
+        for (out, expected) in output[4..].iter().zip([4.0, 3.0, 2.0, 1.0]) {
+            assert!(*out > 0.0 && *out <= expected);
+        }
+        assert_approx_eq!(output[6], 2.0 * 0.75 * 0.75);
+    }
+
+    #[test]
+    fn swells_in() {
+        let mut reverser = Reverser::new(100);
+        let output = run(&mut reverser, &[1.0; 200], 100, 1.0);
+        assert!(output[100..199].windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[test]
+    fn shorter_segment_closes_playback() {
+        let mut reverser = Reverser::new(8);
+        run(&mut reverser, &[1.0; 8], 8, 2.0);
+        let output = run(&mut reverser, &[1.0; 4], 4, 2.0);
+        // We cut off playback of the longer segment, so the envelope closes early.
+        assert!(output[3] < output[2]);
+        assert_approx_eq!(output[3], 0.5 * 0.5 * 0.5);
+    }
+
+    #[test]
+    fn reset() {
+        let mut reverser = Reverser::new(4);
+        run(&mut reverser, &[1.0; 6], 4, 1.0);
+        reverser.reset();
+        assert!(
+            run(&mut reverser, &[0.0; 8], 4, 1.0)
+                .iter()
+                .all(|x| *x == 0.0)
+        );
+    }
+}
@@ -58,6 +58,43 @@ pub fn generate_snapshot_with_params(
     output
 }
 
+/// Generate a stereo snapshot of the effect with the given parameters.
+///
+/// `input` holds the left and right channels.
+///
+/// # Panics
+///
+/// This function panics if the channels are different lengths.
+pub fn generate_stereo_snapshot_with_params(
+    effect: &mut impl Effect,
+    input: [&[f32]; 2],
+    max_buffer_size: usize,
+    params: &impl BufferStates,
+) -> [Vec<f32>; 2] {
+    let [left, right] = input;
+    assert_eq!(left.len(), right.len());
+    let mut output = [vec![0.0; left.len()], vec![0.0; right.len()]];
+
+    // Split input into chunks of max_buffer_size
+    for start in (0..left.len()).step_by(max_buffer_size) {
+        let end = (start + max_buffer_size).min(left.len());
+        let mut input_data = BufferData::new(ChannelLayout::Stereo, end - start);
+        move_into(left[start..end].iter().copied(), input_data.channel_mut(0));
+        move_into(right[start..end].iter().copied(), input_data.channel_mut(1));
+        let mut output_buffer = BufferData::new(ChannelLayout::Stereo, end - start);
+        effect.process(
+            &SnapshotProcessContext { parameters: params },
+            &input_data,
+            &mut output_buffer,
+        );
+        for (channel, output) in output.iter_mut().enumerate() {
+            output[start..end].copy_from_slice(output_buffer.channel(channel));
+        }
+    }
+
+    output
+}
+
 /// Generate a snapshot of the effect with the given processing parameters and parameter overrides.
 pub fn generate_snapshot<S: ::std::hash::BuildHasher>(
     component: &impl Component<Processor: Effect>,
@@ -132,3 +169,52 @@ pub fn generate_snapshot_with_reset<S: ::std::hash::BuildHasher>(
     );
     (before, after)
 }
+
+/// Generate a stereo snapshot of the effect with the given processing parameters and
+/// parameter overrides.
+///
+/// # Panics
+///
+/// This function panics if the channels are different lengths.
+pub fn generate_stereo_snapshot<S: ::std::hash::BuildHasher>(
+    component: &impl Component<Processor: Effect>,
+    input: [&[f32]; 2],
+    processing_params: &ProcessingParams,
+    param_overrides: &HashMap<&'_ str, InternalValue, S>,
+) -> [Vec<f32>; 2] {
+    let mut effect = component.create_processor(&ProcessingEnvironment {
+        sampling_rate: processing_params.sampling_rate,
+        max_samples_per_process_call: processing_params.max_buffer_size,
+        channel_layout: ChannelLayout::Stereo,
+        processing_mode: processing_params.processing_mode,
+    });
+    let params = ConstantBufferStates::new(StatesMap::from(override_defaults(
+        component.parameter_infos().iter().map(Into::into),
+        param_overrides,
+    )));
+    effect.set_processing(true);
+    generate_stereo_snapshot_with_params(
+        &mut effect,
+        input,
+        processing_params.max_buffer_size,
+        &params,
+    )
+}
+
+/// Generate a stereo snapshot of the effect with default processing parameters.
+///
+/// # Panics
+///
+/// This function panics if the channels are different lengths.
+pub fn generate_basic_stereo_snapshot<S: ::std::hash::BuildHasher>(
+    component: &impl Component<Processor: Effect>,
+    input: [&[f32]; 2],
+    param_overrides: &HashMap<&'_ str, InternalValue, S>,
+) -> [Vec<f32>; 2] {
+    generate_stereo_snapshot(
+        component,
+        input,
+        &ProcessingParams::default(),
+        param_overrides,
+    )
+}
@@ -1,2 +1,3 @@
 pub mod dc_blocker;
+pub mod downsampler;
 pub mod svf;
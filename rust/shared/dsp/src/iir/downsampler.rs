@@ -24,6 +24,7 @@ pub struct Downsampler {
 }
 
 impl Downsampler {
+    /// Process two input samples at the high rate, producing one output sample.
     pub fn process(&mut self, input: [f32; 2]) -> f32 {
         let ia0 = input[1];
         let ia1 = (ia0 - self.a1) * 0.061_861_712 + self.a0;
@@ -50,7 +51,7 @@ impl Downsampler {
 #[cfg(test)]
 mod tests {
     use assert_approx_eq::assert_approx_eq;
-    use dsp::test_utils::{estimate_tuning, linear_sine_sweep};
+    use crate::test_utils::{estimate_tuning, linear_sine_sweep, sine};
     use snapshots::assert_snapshot;
 
     use super::*;
@@ -68,7 +69,7 @@ mod tests {
     fn resamples_sine_approximately_correct_tuning() {
         let incr = 18.0 / 1024.0;
         assert_approx_eq!(
-            estimate_tuning(&mut downsample(&dsp::test_utils::sine(1024, incr))),
+            estimate_tuning(&mut downsample(&sine(1024, incr))),
             incr * 2.0
         );
     }
@@ -76,7 +77,7 @@ mod tests {
     #[test]
     fn approximately_preserves_energy() {
         #![allow(clippy::cast_precision_loss)]
-        let sin = dsp::test_utils::sine(1024, 18.0 / 1024.0);
+        let sin = sine(1024, 18.0 / 1024.0);
         let downsampled = downsample(&sin);
         let original_energy = sin.iter().map(|x| x * x).sum::<f32>() / sin.len() as f32;
         let downsampled_energy =
@@ -84,7 +84,66 @@ export const typedInfos = {
     type_specific: {
       t: "enum" as const,
       default: "Synth",
-      values: ["Synth", "Dimension", "Pedal", "Jazz", "Ens 1", "Ens 2"],
+      values: [
+        "Synth",
+        "Dimension",
+        "Pedal",
+        "Jazz",
+        "Ens 1",
+        "Ens 2",
+        "Flanger",
+      ],
+    } as const,
+  } as const,
+  feedback: {
+    title: "Feedback",
+    type_specific: {
+      t: "numeric" as const,
+      default: 0,
+      valid_range: [-95, 95] satisfies [number, number],
+      units: "%",
+    } as const,
+  } as const,
+  base_delay: {
+    title: "Base Delay",
+    type_specific: {
+      t: "numeric" as const,
+      default: 3.505,
+      valid_range: [1, 30] satisfies [number, number],
+      units: "ms",
+    } as const,
+  } as const,
+  bbd_stages: {
+    title: "BBD Stages",
+    type_specific: {
+      t: "enum" as const,
+      default: "256",
+      values: ["256", "512", "1024", "2048", "4096"],
+    } as const,
+  } as const,
+  vintage: {
+    title: "Vintage",
+    type_specific: {
+      t: "numeric" as const,
+      default: 0,
+      valid_range: [0, 100] satisfies [number, number],
+      units: "%",
+    } as const,
+  } as const,
+  mix_mode: {
+    title: "Mix Mode",
+    type_specific: {
+      t: "enum" as const,
+      default: "Add",
+      values: ["Add", "Balance"],
+    } as const,
+  } as const,
+  lfo_shape: {
+    title: "LFO Shape",
+    type_specific: {
+      t: "enum" as const,
+      default: "Triangle",
+      values: ["Triangle", "Sine", "Random"],
     } as const,
   } as const,
 } as const;
@@ -45,6 +45,24 @@ const infos = new Map<string, Info>(
         units: "s",
       },
     },
+    bass_multiplier: {
+      title: "Bass Decay Multiplier",
+      type_specific: {
+        t: "numeric",
+        default: 1,
+        valid_range: [0.5, 2],
+        units: "x",
+      },
+    },
+    bass_crossover: {
+      title: "Bass Crossover",
+      type_specific: {
+        t: "numeric",
+        default: 250,
+        valid_range: [50, 1000],
+        units: "Hz",
+      },
+    },
     early_reflections: {
       title: "Early Reflections Character",
       type_specific: {
@@ -63,6 +81,177 @@ const infos = new Map<string, Info>(
         units: "%",
       },
     },
+    low_cut: {
+      title: "Low Cut",
+      type_specific: {
+        t: "numeric",
+        default: 20,
+        valid_range: [20, 2000],
+        units: "Hz",
+      },
+    },
+    high_cut: {
+      title: "High Cut",
+      type_specific: {
+        t: "numeric",
+        default: 20000,
+        valid_range: [1000, 20000],
+        units: "Hz",
+      },
+    },
+    size: {
+      title: "Size",
+      type_specific: {
+        t: "numeric",
+        default: 100,
+        valid_range: [25, 200],
+        units: "%",
+      },
+    },
+    width: {
+      title: "Width",
+      type_specific: {
+        t: "numeric",
+        default: 100,
+        valid_range: [0, 200],
+        units: "%",
+      },
+    },
+    mono_input: {
+      title: "Mono Input",
+      type_specific: {
+        t: "switch",
+        default: false,
+      },
+    },
+    pre_delay: {
+      title: "Pre-Delay",
+      type_specific: {
+        t: "numeric",
+        default: 0,
+        valid_range: [0, 500],
+        units: "ms",
+      },
+    },
+    freeze: {
+      title: "Freeze",
+      type_specific: {
+        t: "switch",
+        default: false,
+      },
+    },
+    modulation_depth: {
+      title: "Modulation Depth",
+      type_specific: {
+        t: "numeric",
+        default: 20,
+        valid_range: [0, 40],
+        units: "ms",
+      },
+    },
+    modulation_rate: {
+      title: "Modulation Rate",
+      type_specific: {
+        t: "numeric",
+        default: 0.02,
+        valid_range: [0.01, 5],
+        units: "Hz",
+      },
+    },
+    modulate_all_lines: {
+      title: "Modulate All Lines",
+      type_specific: {
+        t: "switch",
+        default: false,
+      },
+    },
+    shimmer: {
+      title: "Shimmer",
+      type_specific: {
+        t: "numeric",
+        default: 0,
+        valid_range: [0, 100],
+        units: "%",
+      },
+    },
+    shimmer_interval: {
+      title: "Shimmer Interval",
+      type_specific: {
+        t: "enum",
+        default: "+12",
+        values: ["+12", "+7", "-12"],
+      },
+    },
+    duck_amount: {
+      title: "Ducking",
+      type_specific: {
+        t: "numeric",
+        default: 0,
+        valid_range: [0, 100],
+        units: "%",
+      },
+    },
+    duck_attack: {
+      title: "Ducking Attack",
+      type_specific: {
+        t: "numeric",
+        default: 10,
+        valid_range: [0.1, 100],
+        units: "ms",
+      },
+    },
+    duck_release: {
+      title: "Ducking Release",
+      type_specific: {
+        t: "numeric",
+        default: 250,
+        valid_range: [10, 2000],
+        units: "ms",
+      },
+    },
+    mode: {
+      title: "Mode",
+      type_specific: {
+        t: "enum",
+        default: "Natural",
+        values: ["Natural", "Gated", "Reverse"],
+      },
+    },
+    gate_hold: {
+      title: "Gate Hold",
+      type_specific: {
+        t: "numeric",
+        default: 300,
+        valid_range: [10, 2000],
+        units: "ms",
+      },
+    },
+    gate_release: {
+      title: "Gate Release",
+      type_specific: {
+        t: "numeric",
+        default: 50,
+        valid_range: [1, 1000],
+        units: "ms",
+      },
+    },
+    gate_threshold: {
+      title: "Gate Threshold",
+      type_specific: {
+        t: "numeric",
+        default: -40,
+        valid_range: [-80, 0],
+        units: "dB",
+      },
+    },
+    engine: {
+      title: "Engine",
+      type_specific: {
+        t: "enum",
+        default: "Algorithmic",
+        values: ["Algorithmic", "Convolution"],
+      },
+    },
   }),
 );
 